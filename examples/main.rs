@@ -5,12 +5,9 @@ use synac::{Session, State};
 fn main() {
     // TODO: Error checking
     let mut session = Session::new(env!("ADDR"), env!("HASH")).unwrap();
-    session.login_with_token(true, env!("USERNAME"), env!("TOKEN")).unwrap(); // true specifies it's a bot account
-
-    // First packet should be either LoginSuccess or an error
-    let result = session.read().unwrap();
-    // TODO: Use result
-    println!("{:?}", result);
+    let login = session.login(true, env!("USERNAME"), env!("TOKEN")).unwrap(); // true specifies it's a bot account
+    // TODO: Save login.token for next time
+    println!("{:?}", login);
 
     let mut state = State::new();
     loop {
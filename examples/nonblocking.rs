@@ -7,18 +7,22 @@ use synac::{Listener, Session, State};
 fn main() {
     // TODO: Error checking
     let mut session = Session::new(env!("ADDR"), env!("HASH")).unwrap();
-    session.login_with_token(true, env!("USERNAME"), env!("TOKEN")).unwrap(); // true specifies it's a bot account
-
-    // First packet should be either LoginSuccess or an error
-    let result = session.read().unwrap();
-    // TODO: Use result
-    println!("{:?}", result);
+    let login = session.login(true, env!("USERNAME"), env!("TOKEN")).unwrap(); // true specifies it's a bot account
+    // TODO: Save login.token for next time
+    println!("{:?}", login);
 
     session.set_nonblocking(true).unwrap();
 
     let mut state = State::new();
     let mut listener = Listener::new();
 
+    for packet in session.buffered() {
+        state.update(&packet);
+
+        // TODO: Use packet
+        println!("{:?}", packet);
+    }
+
     loop {
         let packet = listener.try_read(session.inner_stream()).unwrap(); // <- non blocking
         if let Some(packet) = packet {
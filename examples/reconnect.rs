@@ -0,0 +1,20 @@
+extern crate synac;
+
+use synac::{Event, Supervisor};
+
+fn main() {
+    // TODO: Error checking
+    let mut supervisor = Supervisor::new(env!("ADDR"), env!("HASH"), true, env!("USERNAME"), env!("TOKEN")).unwrap(); // true specifies it's a bot account
+
+    loop {
+        match supervisor.next_event().unwrap() {
+            Event::Connected(login) => println!("Connected as {}", login.id),
+            Event::Disconnected(err) => println!("Disconnected: {}", err),
+            Event::Reconnecting { attempt, delay } => println!("Reconnecting in {:?} (attempt {})", delay, attempt),
+            Event::Packet(packet) => {
+                // TODO: Use packet
+                println!("{:?}", packet);
+            }
+        }
+    }
+}
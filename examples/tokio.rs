@@ -1,34 +1,24 @@
 extern crate futures;
 extern crate synac;
-extern crate tokio_core;
+extern crate tokio;
 
-use futures::Future;
-use std::cell::RefCell;
-use std::net::ToSocketAddrs;
-use synac::{Session, State};
-use tokio_core::reactor::Core;
+use futures::StreamExt;
+use synac::{AsyncSession, State};
 
-fn main() {
+#[tokio::main]
+async fn main() {
     // TODO: Error checking
+    let mut session = AsyncSession::new(env!("ADDR"), env!("HASH")).await.unwrap();
+    let login = session.login(true, env!("USERNAME"), env!("TOKEN")).await.unwrap(); // true specifies it's a bot account
+    // TODO: Save login.token for next time
+    println!("{:?}", login);
 
-    let mut core = Core::new().unwrap();
-    let session = Session::new(
-        &env!("ADDR").to_socket_addrs().unwrap().next().unwrap(),
-        env!("HASH"),
-        &core.handle()
-    );
-    let mut session = core.run(session).unwrap();
-
-    session.login_with_token(true, env!("USERNAME"), env!("TOKEN")).unwrap(); // true specifies it's a bot account
-
-    // First packet should be either LoginSuccess or an error
-
-    let state = RefCell::new(State::new());
-
-    core.run(session.read_loop(move |packet| {
-        state.borrow_mut().update(&packet);
+    let mut state = State::new();
+    while let Some(packet) = session.next().await {
+        let packet = packet.unwrap();
+        state.update(&packet);
 
         // TODO: Use packet
         println!("{:?}", packet);
-    }).map_err(|_| ())).unwrap();
+    }
 }
@@ -0,0 +1,246 @@
+use crate::builder::SessionBuilder;
+use crate::codec::Codec;
+use crate::common::{self, Packet};
+use crate::error::ServerError;
+use crate::fingerprint::Fingerprint;
+use crate::history::{self, Direction, HistoryPager};
+use crate::invocations::Invocations;
+use crate::mode::Mode;
+use crate::state::State;
+use crate::typing::Clock;
+#[cfg(feature = "tokio")] use crate::tls_openssl;
+#[cfg(feature = "tokio-rustls")] use crate::tls_rustls;
+use crate::LoginOutcome;
+use failure::Error;
+use futures::{Sink, SinkExt, Stream as FuturesStream, StreamExt};
+#[cfg(feature = "tokio")] use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+#[cfg(feature = "tokio")] use openssl::x509::X509StoreContextRef;
+#[cfg(feature = "tokio")] use std::any::Any;
+use std::collections::VecDeque;
+use std::collections::vec_deque::Drain;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, ToSocketAddrs};
+#[cfg(feature = "tokio")] use tokio_openssl::SslStream;
+#[cfg(feature = "tokio-rustls")] use tokio_rustls::client::TlsStream;
+use tokio_util::codec::Framed;
+
+#[cfg(feature = "tokio")]
+/// TLS over TCP using openssl
+pub type OpensslStream = SslStream<TcpStream>;
+#[cfg(feature = "tokio-rustls")]
+/// TLS over TCP using rustls
+pub type RustlsStream = TlsStream<TcpStream>;
+#[cfg(feature = "tokio")]
+/// The transport `Session::new` connects with: openssl if the `tokio` feature is enabled, otherwise rustls
+pub type Stream = OpensslStream;
+#[cfg(not(feature = "tokio"))]
+/// The transport `Session::new` connects with: openssl if the `tokio` feature is enabled, otherwise rustls
+pub type Stream = RustlsStream;
+
+/// A struct that holds the connection to synac.
+/// Incoming packets are read as a `Stream`, outgoing packets can be sent as a `Sink`.
+/// The `Stream` yields `Packet::Err` as is, map it through `ServerError::check` to get errors instead.
+/// `T` is the transport, which is TLS over TCP unless the session is created with `from_stream`.
+pub struct Session<T = Stream> {
+    framed: Framed<T, Codec>,
+    buffer: VecDeque<Packet>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>
+}
+
+impl Session {
+    /// Create a synac session that verifies the public key against a hash.
+    /// Servers only supporting TLS older than 1.2 are refused.
+    /// See `SessionBuilder` for more options.
+    pub async fn new<S: Into<String>, T: ToSocketAddrs>(addr: T, hash: S) -> Result<Session, Error> {
+        SessionBuilder::new().pin(hash).connect_async(addr).await
+    }
+}
+#[cfg(feature = "tokio")]
+impl Session<OpensslStream> {
+    /// Create a synac session with a custom SSL callback.
+    pub async fn new_with_verify_callback<T, F>(addr: T, callback: F)
+        -> Result<Session<OpensslStream>, Error>
+        where
+            T: ToSocketAddrs,
+            F: Fn(bool, &mut X509StoreContextRef) -> bool + Any + 'static + Sync + Send
+    {
+        let mut config = SslConnector::builder(SslMethod::tls())?;
+        config.set_verify_callback(SslVerifyMode::PEER, callback);
+        let connector = config.build();
+        let ssl = connector.configure()?
+                    .use_server_name_indication(false)
+                    .verify_hostname(false)
+                    .into_ssl("")?;
+
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = SslStream::new(ssl, stream)?;
+        Pin::new(&mut stream).connect().await?;
+
+        Ok(Session::from_stream(stream))
+    }
+    /// Returns the fingerprint of the public key the server used
+    pub fn peer_fingerprint(&self) -> Option<Fingerprint> {
+        tls_openssl::peer_fingerprint(self.framed.get_ref().ssl())
+    }
+}
+#[cfg(feature = "tokio-rustls")]
+impl Session<RustlsStream> {
+    /// Returns the fingerprint of the public key the server used
+    pub fn peer_fingerprint(&self) -> Option<Fingerprint> {
+        tls_rustls::peer_fingerprint(self.framed.get_ref().get_ref().1)
+    }
+}
+impl<T: AsyncRead + AsyncWrite + Unpin> Session<T> {
+    /// Create a session over an already connected transport,
+    /// such as a Unix socket, an in-memory pipe or plain TCP for local testing
+    pub fn from_stream(stream: T) -> Session<T> {
+        Session {
+            framed: Framed::new(stream, Codec::new()),
+            buffer: VecDeque::new(),
+            read_timeout: None,
+            write_timeout: None
+        }
+    }
+    /// Fail `read` if it takes longer than `timeout`
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+    /// Fail `write` if it takes longer than `timeout`
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write_timeout = timeout;
+    }
+
+    /// Returns inner connection
+    pub fn inner_stream(&mut self) -> &mut T {
+        self.framed.get_mut()
+    }
+
+    /// Logs in with specific token and waits for the result.
+    /// Any unrelated packets received in the meantime are buffered and returned by `read`,
+    /// followed by the `LoginSuccess` packet itself so `State` can pick it up.
+    pub async fn login<S: Into<String>>(&mut self, bot: bool, name: S, token: S) -> Result<LoginOutcome, Error> {
+        self.login_with_token(bot, name, token).await?;
+        self.wait_for_login().await
+    }
+    /// Logs in with specific password and waits for the result.
+    /// Any unrelated packets received in the meantime are buffered and returned by `read`,
+    /// followed by the `LoginSuccess` packet itself so `State` can pick it up.
+    /// Warning: Strongly disencouraged. Use tokens instead, when possible.
+    pub async fn login_password<S: Into<String>>(&mut self, bot: bool, name: S, password: S) -> Result<LoginOutcome, Error> {
+        self.login_with_password(bot, name, password).await?;
+        self.wait_for_login().await
+    }
+    async fn wait_for_login(&mut self) -> Result<LoginOutcome, Error> {
+        loop {
+            let packet = self.read_unbuffered().await?;
+            if let Some(result) = crate::handle_login(&mut self.buffer, packet) {
+                return result;
+            }
+        }
+    }
+
+    request_methods!(async);
+
+    /// Makes sure you have a private channel with `user`.
+    /// Returns the id of the existing channel if `state` knows one,
+    /// otherwise requests a new one which will arrive as a `ChannelReceive` and returns `None`.
+    pub async fn open_dm(&mut self, state: &State, user: usize) -> Result<Option<usize>, Error> {
+        if let Some(channel) = state.dm_channel(user) {
+            return Ok(Some(channel.id));
+        }
+        self.create_private_channel(user).await?;
+        Ok(None)
+    }
+    /// Sends a command to `bot` and tracks it in `invocations`, which correlates the bot's replies with it.
+    /// Returns the id of the invocation.
+    pub async fn invoke_command<C: Clock>(&mut self, invocations: &mut Invocations<C>, bot: usize, args: Vec<String>) -> Result<usize, Error> {
+        let (id, packet) = invocations.invoke(bot, args);
+        self.write(&packet).await?;
+        Ok(id)
+    }
+
+    /// Transmit a packet over the connection
+    pub async fn write(&mut self, packet: &Packet) -> Result<(), Error> {
+        timeout(self.write_timeout, self.framed.send(packet)).await
+    }
+
+    /// Read a packet from the connection
+    pub async fn read(&mut self) -> Result<Packet, Error> {
+        if let Some(packet) = self.buffer.pop_front() {
+            return Ok(packet);
+        }
+        self.read_unbuffered().await
+    }
+    /// Read a packet from the connection,
+    /// returning `Packet::Err` as a `ServerError` instead.
+    pub async fn read_checked(&mut self) -> Result<Packet, Error> {
+        Ok(ServerError::check(self.read().await?)?)
+    }
+    pub(crate) async fn read_unbuffered(&mut self) -> Result<Packet, Error> {
+        let framed = &mut self.framed;
+        timeout(self.read_timeout, async move {
+            match framed.next().await {
+                Some(result) => result,
+                None => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+            }
+        }).await
+    }
+
+    /// Walk through the history of `channel`, requesting `page_size` messages at a time.
+    /// Unrelated packets received in the meantime are buffered and returned by `read`.
+    pub fn history(&mut self, channel: usize, direction: Direction, page_size: usize)
+        -> impl FuturesStream<Item = Result<common::Message, Error>> + '_
+    {
+        history::history_stream(self, HistoryPager::new(channel, direction, page_size))
+    }
+    /// Takes all packets that were buffered while waiting for something else.
+    /// `read` already returns these first, but a `Listener` reading `inner_stream` won't.
+    pub fn buffered(&mut self) -> Drain<'_, Packet> {
+        self.buffer.drain(..)
+    }
+    pub(crate) fn buffer_packet(&mut self, packet: Packet) {
+        self.buffer.push_back(packet);
+    }
+}
+
+async fn timeout<T, F: Future<Output = Result<T, Error>>>(limit: Option<Duration>, future: F) -> Result<T, Error> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, future).await
+            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?,
+        None => future.await
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> FuturesStream for Session<T> {
+    type Item = Result<Packet, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let session = self.get_mut();
+        if let Some(packet) = session.buffer.pop_front() {
+            return Poll::Ready(Some(Ok(packet)));
+        }
+        Pin::new(&mut session.framed).poll_next(cx)
+    }
+}
+impl<T: AsyncRead + AsyncWrite + Unpin> Sink<Packet> for Session<T> {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Sink::<Packet>::poll_ready(Pin::new(&mut self.get_mut().framed), cx)
+    }
+    fn start_send(self: Pin<&mut Self>, packet: Packet) -> Result<(), Error> {
+        Pin::new(&mut self.get_mut().framed).start_send(packet)
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Sink::<Packet>::poll_flush(Pin::new(&mut self.get_mut().framed), cx)
+    }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Sink::<Packet>::poll_close(Pin::new(&mut self.get_mut().framed), cx)
+    }
+}
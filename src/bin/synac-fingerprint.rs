@@ -0,0 +1,76 @@
+extern crate synac;
+
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::process;
+use synac::common::DEFAULT_PORT;
+use synac::{SessionBuilder, Verification};
+
+const USAGE: &str = "Usage: synac-fingerprint [--colon | --base64] [--server-name <name>] <address[:port]>";
+
+/// Add the default port to `addr` unless it has one. IPv6 addresses may be in brackets.
+fn with_default_port(addr: String) -> String {
+    if addr.parse::<SocketAddr>().is_ok() {
+        return addr;
+    }
+    if let Ok(ip) = addr.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        return SocketAddr::new(ip, DEFAULT_PORT).to_string();
+    }
+    match addr.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => addr,
+        _ => format!("{}:{}", addr, DEFAULT_PORT)
+    }
+}
+
+fn main() {
+    let mut format = "hex";
+    let mut server_name = None;
+    let mut addr = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "--hex" => format = "hex",
+            "--colon" => format = "colon",
+            "--base64" => format = "base64",
+            "--server-name" => server_name = args.next(),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            },
+            _ if addr.is_none() && !arg.starts_with('-') => addr = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(1);
+            }
+        }
+    }
+    let addr = match addr {
+        Some(addr) => with_default_port(addr),
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(1);
+        }
+    };
+
+    let mut builder = SessionBuilder::new().verification(Verification::None);
+    if let Some(name) = server_name {
+        builder = builder.server_name(name);
+    }
+    let fingerprint = match builder.connect(&*addr).map(|session| session.peer_fingerprint()) {
+        Ok(Some(fingerprint)) => fingerprint,
+        Ok(None) => {
+            eprintln!("error: server has no public key");
+            process::exit(1);
+        },
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    };
+    match format {
+        "colon" => println!("{}", fingerprint.to_colon_hex()),
+        "base64" => println!("{}", fingerprint.to_base64()),
+        _ => println!("{}", fingerprint.to_hex())
+    }
+}
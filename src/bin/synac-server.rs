@@ -0,0 +1,79 @@
+extern crate synac;
+
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::process;
+use std::thread;
+use std::time::Duration;
+use synac::common::DEFAULT_PORT;
+use synac::server::Server;
+
+const USAGE: &str = "Usage: synac-server [--data <file>] [--admin <name>] [address[:port]]";
+
+/// Add the default port to `addr` unless it has one. IPv6 addresses may be in brackets.
+fn with_default_port(addr: String) -> String {
+    if addr.parse::<SocketAddr>().is_ok() {
+        return addr;
+    }
+    if let Ok(ip) = addr.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        return SocketAddr::new(ip, DEFAULT_PORT).to_string();
+    }
+    match addr.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => addr,
+        _ => format!("{}:{}", addr, DEFAULT_PORT)
+    }
+}
+
+fn main() {
+    let mut data = None;
+    let mut admin = None;
+    let mut addr = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "--data" => data = args.next(),
+            "--admin" => admin = args.next(),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            },
+            _ if addr.is_none() && !arg.starts_with('-') => addr = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(1);
+            }
+        }
+    }
+    let addr = with_default_port(addr.unwrap_or_else(|| String::from("127.0.0.1")));
+
+    let server = match data {
+        Some(ref path) => Server::open(path).unwrap_or_else(|err| {
+            eprintln!("error: failed to load {}: {}", path, err);
+            process::exit(1);
+        }),
+        None => Server::new()
+    };
+    match server.listen(&*addr) {
+        Ok(addr) => println!("Listening on {} (plain TCP, use Session::from_stream)", addr),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    }
+
+    loop {
+        thread::sleep(Duration::from_secs(5));
+        // the admin can only be set once the account exists
+        if let Some(user) = admin.as_ref().and_then(|name| server.user_by_name(name)) {
+            if !user.admin {
+                server.set_admin(user.id, true);
+            }
+        }
+        if data.is_some() {
+            if let Err(err) = server.save() {
+                eprintln!("error: failed to save: {}", err);
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::builder::SessionBuilder;
+use crate::common::{self, Packet};
+use crate::error::ServerError;
+use crate::fingerprint::Fingerprint;
+use crate::history::{Direction, History, HistoryPager};
+use crate::invocations::Invocations;
+use crate::listener::Listener;
+use crate::mode::Mode;
+use crate::state::State;
+use crate::typing::Clock;
+use crate::LoginOutcome;
+use failure::Error;
+#[cfg(feature = "openssl")] use openssl::ssl::{SslConnector, SslMethod, SslStream, SslVerifyMode};
+#[cfg(feature = "openssl")] use openssl::x509::X509StoreContextRef;
+#[cfg(feature = "rustls")] use rustls::{ClientConnection, StreamOwned};
+#[cfg(feature = "openssl")] use crate::tls_openssl;
+#[cfg(feature = "rustls")] use crate::tls_rustls;
+#[cfg(feature = "openssl")] use std::any::Any;
+use std::collections::VecDeque;
+use std::collections::vec_deque::Drain;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+#[cfg(feature = "openssl")]
+/// TLS over TCP using openssl
+pub type OpensslStream = SslStream<TcpStream>;
+#[cfg(feature = "rustls")]
+/// TLS over TCP using rustls
+pub type RustlsStream = StreamOwned<ClientConnection, TcpStream>;
+#[cfg(feature = "openssl")]
+/// The transport `Session::new` connects with: openssl if enabled, otherwise rustls
+pub type Stream = OpensslStream;
+#[cfg(not(feature = "openssl"))]
+/// The transport `Session::new` connects with: openssl if enabled, otherwise rustls
+pub type Stream = RustlsStream;
+
+/// A struct that holds the connection to synac.
+/// `T` is the transport, which is TLS over TCP unless the session is created with `from_stream`.
+pub struct Session<T = Stream> {
+    stream: T,
+    buffer: VecDeque<Packet>,
+    // keeps partial packets when a read times out
+    listener: Listener
+}
+
+impl Session {
+    /// Create a synac session that verifies the public key against a hash.
+    /// Servers only supporting TLS older than 1.2 are refused.
+    /// See `SessionBuilder` for more options.
+    pub fn new<S: Into<String>, T: ToSocketAddrs>(addr: T, hash: S) -> Result<Session, Error> {
+        SessionBuilder::new().pin(hash).connect(addr)
+    }
+}
+#[cfg(feature = "openssl")]
+impl Session<OpensslStream> {
+    /// Create a synac session with a custom SSL callback.
+    pub fn new_with_verify_callback<T, F>(addr: T, callback: F)
+        -> Result<Session<OpensslStream>, Error>
+        where
+            T: ToSocketAddrs,
+            F: Fn(bool, &mut X509StoreContextRef) -> bool + Any + 'static + Sync + Send
+    {
+        let mut config = SslConnector::builder(SslMethod::tls())?;
+        config.set_verify_callback(SslVerifyMode::PEER, callback);
+        let connector = config.build();
+
+        let stream = TcpStream::connect(addr)?;
+        let stream = connector.configure()?
+                        .use_server_name_indication(false)
+                        .verify_hostname(false)
+                        .connect("", stream)?;
+
+        Ok(Session::from_stream(stream))
+    }
+    /// Returns the fingerprint of the public key the server used
+    pub fn peer_fingerprint(&self) -> Option<Fingerprint> {
+        tls_openssl::peer_fingerprint(self.stream.ssl())
+    }
+
+    /// Makes inner stream non-blocking
+    pub fn set_nonblocking(&mut self, value: bool) -> Result<(), std::io::Error> {
+        self.stream.get_ref().set_nonblocking(value)
+    }
+}
+#[cfg(feature = "rustls")]
+impl Session<RustlsStream> {
+    /// Returns the fingerprint of the public key the server used
+    pub fn peer_fingerprint(&self) -> Option<Fingerprint> {
+        tls_rustls::peer_fingerprint(&self.stream.conn)
+    }
+
+    /// Makes inner stream non-blocking
+    pub fn set_nonblocking(&mut self, value: bool) -> Result<(), std::io::Error> {
+        self.stream.get_ref().set_nonblocking(value)
+    }
+}
+impl<T: Read + Write> Session<T> {
+    /// Create a session over an already connected transport,
+    /// such as a Unix socket, an in-memory pipe or plain TCP for local testing
+    pub fn from_stream(stream: T) -> Session<T> {
+        Session {
+            stream,
+            buffer: VecDeque::new(),
+            listener: Listener::new()
+        }
+    }
+
+    /// Returns inner connection
+    pub fn inner_stream(&mut self) -> &mut T {
+        &mut self.stream
+    }
+
+    /// Logs in with specific token and waits for the result.
+    /// Any unrelated packets received in the meantime are buffered and returned by `read`,
+    /// followed by the `LoginSuccess` packet itself so `State` can pick it up.
+    pub fn login<S: Into<String>>(&mut self, bot: bool, name: S, token: S) -> Result<LoginOutcome, Error> {
+        self.login_with_token(bot, name, token)?;
+        self.wait_for_login()
+    }
+    /// Logs in with specific password and waits for the result.
+    /// Any unrelated packets received in the meantime are buffered and returned by `read`,
+    /// followed by the `LoginSuccess` packet itself so `State` can pick it up.
+    /// Warning: Strongly disencouraged. Use tokens instead, when possible.
+    pub fn login_password<S: Into<String>>(&mut self, bot: bool, name: S, password: S) -> Result<LoginOutcome, Error> {
+        self.login_with_password(bot, name, password)?;
+        self.wait_for_login()
+    }
+    fn wait_for_login(&mut self) -> Result<LoginOutcome, Error> {
+        loop {
+            let packet = self.read_unbuffered()?;
+            if let Some(result) = crate::handle_login(&mut self.buffer, packet) {
+                return result;
+            }
+        }
+    }
+
+    request_methods!(blocking);
+
+    /// Makes sure you have a private channel with `user`.
+    /// Returns the id of the existing channel if `state` knows one,
+    /// otherwise requests a new one which will arrive as a `ChannelReceive` and returns `None`.
+    pub fn open_dm(&mut self, state: &State, user: usize) -> Result<Option<usize>, Error> {
+        if let Some(channel) = state.dm_channel(user) {
+            return Ok(Some(channel.id));
+        }
+        self.create_private_channel(user)?;
+        Ok(None)
+    }
+    /// Sends a command to `bot` and tracks it in `invocations`, which correlates the bot's replies with it.
+    /// Returns the id of the invocation.
+    pub fn invoke_command<C: Clock>(&mut self, invocations: &mut Invocations<C>, bot: usize, args: Vec<String>) -> Result<usize, Error> {
+        let (id, packet) = invocations.invoke(bot, args);
+        self.write(&packet)?;
+        Ok(id)
+    }
+
+    /// Transmit a packet over the connection
+    pub fn write(&mut self, packet: &Packet) -> Result<(), Error> {
+        Ok(common::write(&mut self.stream, packet)?)
+    }
+
+    /// Read a packet from the connection.
+    /// If this fails because of a read timeout, reading again continues where it stopped.
+    pub fn read(&mut self) -> Result<Packet, Error> {
+        if let Some(packet) = self.buffer.pop_front() {
+            return Ok(packet);
+        }
+        self.read_unbuffered()
+    }
+    /// Read a packet if one is ready, for a non-blocking session or one with a read timeout.
+    /// Returns `None` instead of failing if the packet isn't complete yet.
+    pub fn try_read(&mut self) -> Result<Option<Packet>, Error> {
+        if let Some(packet) = self.buffer.pop_front() {
+            return Ok(Some(packet));
+        }
+        match self.listener.read(&mut self.stream) {
+            Ok(packet) => Ok(Some(packet)),
+            Err(common::Error::IoError(ref err))
+                if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut
+                => Ok(None),
+            Err(err) => Err(err.into())
+        }
+    }
+    /// Read a packet from the connection,
+    /// returning `Packet::Err` as a `ServerError` instead.
+    pub fn read_checked(&mut self) -> Result<Packet, Error> {
+        Ok(ServerError::check(self.read()?)?)
+    }
+    pub(crate) fn read_unbuffered(&mut self) -> Result<Packet, Error> {
+        Ok(self.listener.read(&mut self.stream)?)
+    }
+
+    /// Walk through the history of `channel`, requesting `page_size` messages at a time.
+    /// Unrelated packets received in the meantime are buffered and returned by `read`.
+    pub fn history(&mut self, channel: usize, direction: Direction, page_size: usize) -> History<'_, T> {
+        History::new(self, HistoryPager::new(channel, direction, page_size))
+    }
+    /// Takes all packets that were buffered while waiting for something else.
+    /// `read` already returns these first, but a `Listener` reading `inner_stream` won't.
+    pub fn buffered(&mut self) -> Drain<'_, Packet> {
+        self.buffer.drain(..)
+    }
+    pub(crate) fn buffer_packet(&mut self, packet: Packet) {
+        self.buffer.push_back(packet);
+    }
+}
@@ -0,0 +1,308 @@
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] use crate::asynchronous;
+#[cfg(feature = "openssl")] use crate::tls_openssl;
+#[cfg(feature = "rustls")] use crate::tls_rustls;
+#[cfg(feature = "rustls")] use rustls::{ClientConnection, StreamOwned};
+#[cfg(feature = "tokio")] use std::pin::Pin;
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] use tokio::io::{AsyncRead, AsyncWrite};
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] use tokio::net::TcpStream as AsyncTcpStream;
+#[cfg(feature = "tokio")] use tokio_openssl::SslStream;
+#[cfg(feature = "tokio-rustls")] use tokio_rustls::TlsConnector;
+use crate::blocking;
+use crate::fingerprint::{self, Fingerprint};
+use crate::known_hosts::KnownHosts;
+use failure::Error;
+use socket2::{SockRef, TcpKeepalive};
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The oldest TLS version to accept
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TlsVersion {
+    Tls12,
+    Tls13
+}
+
+/// How to make sure the server is the right one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verification {
+    /// Accept the server if the SHA-256 hash of its public key matches, like `Session::new` does.
+    /// The certificate itself and its hostname are not checked.
+    Pinned(String),
+    /// Verify the certificate chain against the system's trusted CAs
+    /// and any added with `SessionBuilder::add_ca_file`,
+    /// and that it's valid for the server name. Connecting fails with `MissingServerName` without one.
+    Ca,
+    /// Trust on first use: accept the key of a server that isn't in this known hosts file yet and add it,
+    /// otherwise fail with `HostKeyChanged` unless it's one of the hashes in the file.
+    /// Servers are written as the server name and port, or their address if there's no server name.
+    KnownHosts(PathBuf),
+    /// Accept any server. Anybody could be pretending to be it,
+    /// so this is only useful for looking at its key, like `fingerprint::fetch` does.
+    None
+}
+
+/// Returned when verifying using CAs without a server name to check the certificate against
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MissingServerName;
+
+impl fmt::Display for MissingServerName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "verifying using CAs needs a server name")
+    }
+}
+impl StdError for MissingServerName {}
+
+/// Connects a session with more control over TLS and the socket than `Session::new`
+#[derive(Clone, Debug)]
+pub struct SessionBuilder {
+    pub(crate) verification: Verification,
+    pub(crate) ca_files: Vec<PathBuf>,
+    pub(crate) server_name: Option<String>,
+    pub(crate) sni: bool,
+    pub(crate) min_tls_version: Option<TlsVersion>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    keepalive: Option<Duration>,
+    nodelay: bool
+}
+
+impl Default for SessionBuilder {
+    fn default() -> Self {
+        SessionBuilder {
+            verification: Verification::Ca,
+            ca_files: Vec::new(),
+            server_name: None,
+            sni: true,
+            min_tls_version: None,
+            connect_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            keepalive: None,
+            nodelay: false
+        }
+    }
+}
+impl SessionBuilder {
+    /// Create a new builder. Unless `pin` is called, the server is verified using CAs,
+    /// which needs a `server_name`.
+    pub fn new() -> Self {
+        SessionBuilder::default()
+    }
+
+    /// Verify the server by the hash of its public key
+    pub fn pin<S: Into<String>>(mut self, hash: S) -> Self {
+        self.verification = Verification::Pinned(hash.into());
+        self
+    }
+    /// Verify the server using a known hosts file, see `Verification::KnownHosts`
+    pub fn known_hosts<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.verification = Verification::KnownHosts(path.into());
+        self
+    }
+    /// Set how to verify the server
+    pub fn verification(mut self, verification: Verification) -> Self {
+        self.verification = verification;
+        self
+    }
+    /// Trust the CA certificates in a PEM file, in addition to the system's
+    pub fn add_ca_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.ca_files.push(path.into());
+        self
+    }
+    /// The hostname of the server, sent with SNI and checked against the certificate.
+    /// Required when verifying using CAs.
+    pub fn server_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.server_name = Some(name.into());
+        self
+    }
+    /// Whether to send the server name with SNI. Default is true.
+    pub fn sni(mut self, value: bool) -> Self {
+        self.sni = value;
+        self
+    }
+    /// Refuse anything older than `version`. Default is TLS 1.2.
+    pub fn min_tls_version(mut self, version: TlsVersion) -> Self {
+        self.min_tls_version = Some(version);
+        self
+    }
+
+    /// Give up connecting to an address after `timeout`, not including the TLS handshake
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+    /// Fail reads that take longer than `timeout`. Reading again continues with the same packet.
+    /// The async session only applies this to `read`, not when used as a `Stream`.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+    /// Fail writes that take longer than `timeout`.
+    /// Part of the packet may have been sent already, so drop the session after a failed write.
+    /// The async session only applies this to `write`, not when used as a `Sink`.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+    /// Enable TCP keepalive, sending probes after the connection has been idle for `idle`
+    pub fn keepalive(mut self, idle: Duration) -> Self {
+        self.keepalive = Some(idle);
+        self
+    }
+    /// Set TCP_NODELAY, which sends small packets right away instead of batching them
+    pub fn nodelay(mut self, value: bool) -> Self {
+        self.nodelay = value;
+        self
+    }
+
+    #[cfg(feature = "openssl")]
+    /// Connect a blocking session, using openssl if enabled and rustls otherwise
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> Result<blocking::Session, Error> {
+        self.connect_openssl(addr)
+    }
+    #[cfg(not(feature = "openssl"))]
+    /// Connect a blocking session, using openssl if enabled and rustls otherwise
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> Result<blocking::Session, Error> {
+        self.connect_rustls(addr)
+    }
+    #[cfg(feature = "openssl")]
+    /// Connect a blocking session using openssl
+    pub fn connect_openssl<A: ToSocketAddrs>(&self, addr: A) -> Result<blocking::Session<blocking::OpensslStream>, Error> {
+        let stream = self.connect_socket(addr)?;
+        let peer = stream.peer_addr()?;
+        let domain = self.server_name.as_deref().unwrap_or("");
+        let stream = tls_openssl::configure(self)?.connect(domain, stream)?;
+        self.check_known_host(peer, tls_openssl::peer_fingerprint(stream.ssl()))?;
+        Ok(blocking::Session::from_stream(stream))
+    }
+    #[cfg(feature = "rustls")]
+    /// Connect a blocking session using rustls
+    pub fn connect_rustls<A: ToSocketAddrs>(&self, addr: A) -> Result<blocking::Session<blocking::RustlsStream>, Error> {
+        let mut stream = self.connect_socket(addr)?;
+        let name = tls_rustls::server_name(self, stream.peer_addr()?.ip())?;
+        let mut conn = ClientConnection::new(tls_rustls::configure(self)?, name)?;
+        // Handshake right away, so a failed verification is reported here like it is with openssl
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream)?;
+        }
+        self.check_known_host(stream.peer_addr()?, tls_rustls::peer_fingerprint(&conn))?;
+        Ok(blocking::Session::from_stream(StreamOwned::new(conn, stream)))
+    }
+    fn connect_socket<A: ToSocketAddrs>(&self, addr: A) -> Result<TcpStream, Error> {
+        self.check()?;
+        let stream = self.connect_tcp(addr)?;
+        self.configure(SockRef::from(&stream))?;
+        stream.set_read_timeout(self.read_timeout)?;
+        stream.set_write_timeout(self.write_timeout)?;
+        Ok(stream)
+    }
+    fn check(&self) -> Result<(), MissingServerName> {
+        if self.verification == Verification::Ca && self.server_name.is_none() {
+            return Err(MissingServerName);
+        }
+        Ok(())
+    }
+    fn connect_tcp<A: ToSocketAddrs>(&self, addr: A) -> io::Result<TcpStream> {
+        let timeout = match self.connect_timeout {
+            Some(timeout) => timeout,
+            None => return TcpStream::connect(addr)
+        };
+        let mut last_err = None;
+        for addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_err = Some(err)
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any addresses")
+        }))
+    }
+
+    #[cfg(feature = "tokio")]
+    /// Connect an async session, using openssl if the `tokio` feature is enabled and rustls otherwise
+    pub async fn connect_async<A: tokio::net::ToSocketAddrs>(&self, addr: A) -> Result<asynchronous::Session, Error> {
+        self.connect_async_openssl(addr).await
+    }
+    #[cfg(all(feature = "tokio-rustls", not(feature = "tokio")))]
+    /// Connect an async session, using openssl if the `tokio` feature is enabled and rustls otherwise
+    pub async fn connect_async<A: tokio::net::ToSocketAddrs>(&self, addr: A) -> Result<asynchronous::Session, Error> {
+        self.connect_async_rustls(addr).await
+    }
+    #[cfg(feature = "tokio")]
+    /// Connect an async session using openssl
+    pub async fn connect_async_openssl<A: tokio::net::ToSocketAddrs>(&self, addr: A)
+        -> Result<asynchronous::Session<asynchronous::OpensslStream>, Error>
+    {
+        let stream = self.connect_socket_async(addr).await?;
+        let peer = stream.peer_addr()?;
+        let domain = self.server_name.as_deref().unwrap_or("");
+        let ssl = tls_openssl::configure(self)?.into_ssl(domain)?;
+        let mut stream = SslStream::new(ssl, stream)?;
+        Pin::new(&mut stream).connect().await?;
+        self.check_known_host(peer, tls_openssl::peer_fingerprint(stream.ssl()))?;
+        Ok(self.async_session(stream))
+    }
+    #[cfg(feature = "tokio-rustls")]
+    /// Connect an async session using rustls
+    pub async fn connect_async_rustls<A: tokio::net::ToSocketAddrs>(&self, addr: A)
+        -> Result<asynchronous::Session<asynchronous::RustlsStream>, Error>
+    {
+        let stream = self.connect_socket_async(addr).await?;
+        let peer = stream.peer_addr()?;
+        let name = tls_rustls::server_name(self, peer.ip())?;
+        let connector = TlsConnector::from(tls_rustls::configure(self)?);
+        let stream = connector.connect(name, stream).await?;
+        self.check_known_host(peer, tls_rustls::peer_fingerprint(stream.get_ref().1))?;
+        Ok(self.async_session(stream))
+    }
+    #[cfg(any(feature = "tokio", feature = "tokio-rustls"))]
+    async fn connect_socket_async<A: tokio::net::ToSocketAddrs>(&self, addr: A) -> Result<AsyncTcpStream, Error> {
+        self.check()?;
+        let connect = AsyncTcpStream::connect(addr);
+        let stream = match self.connect_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, connect).await
+                .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??,
+            None => connect.await?
+        };
+        self.configure(SockRef::from(&stream))?;
+        Ok(stream)
+    }
+    #[cfg(any(feature = "tokio", feature = "tokio-rustls"))]
+    fn async_session<T: AsyncRead + AsyncWrite + Unpin>(&self, stream: T) -> asynchronous::Session<T> {
+        let mut session = asynchronous::Session::from_stream(stream);
+        session.set_read_timeout(self.read_timeout);
+        session.set_write_timeout(self.write_timeout);
+        session
+    }
+
+    fn check_known_host(&self, peer: SocketAddr, hash: Option<Fingerprint>) -> Result<(), Error> {
+        let path = match self.verification {
+            Verification::KnownHosts(ref path) => path,
+            _ => return Ok(())
+        };
+        let hash = hash.ok_or_else(fingerprint::no_key)?.to_hex();
+        let host = match self.server_name {
+            Some(ref name) => format!("{}:{}", name, peer.port()),
+            None => peer.to_string()
+        };
+
+        let mut hosts = KnownHosts::open(path)?;
+        if hosts.trust(&host, &hash)? {
+            hosts.save()?;
+        }
+        Ok(())
+    }
+    fn configure(&self, socket: SockRef<'_>) -> io::Result<()> {
+        socket.set_tcp_nodelay(self.nodelay)?;
+        if let Some(idle) = self.keepalive {
+            socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+        }
+        Ok(())
+    }
+}
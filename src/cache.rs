@@ -0,0 +1,102 @@
+use crate::common::Message;
+use std::collections::{HashMap, VecDeque};
+
+struct Entry {
+    message: Message,
+    revisions: Vec<Message>
+}
+
+/// Remembers the most recent messages in each channel, up to a limit per channel
+pub struct MessageCache {
+    limit: usize,
+    channels: HashMap<usize, VecDeque<usize>>,
+    messages: HashMap<usize, Entry>
+}
+
+impl MessageCache {
+    /// Create a new cache that keeps at most `limit` messages per channel
+    pub fn new(limit: usize) -> Self {
+        MessageCache {
+            limit,
+            channels: HashMap::new(),
+            messages: HashMap::new()
+        }
+    }
+    /// Returns the maximum amount of messages kept per channel
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Insert a message, or update it if it's already known.
+    /// If the message was edited, the previous version is kept as a revision.
+    pub fn insert(&mut self, message: &Message) {
+        if let Some(entry) = self.messages.get_mut(&message.id) {
+            if entry.message.text != message.text || entry.message.timestamp_edit != message.timestamp_edit {
+                let old = ::std::mem::replace(&mut entry.message, message.clone());
+                entry.revisions.push(old);
+            }
+            return;
+        }
+
+        let ids = self.channels.entry(message.channel).or_default();
+        let index = match ids.binary_search(&message.id) {
+            Ok(index) | Err(index) => index
+        };
+        ids.insert(index, message.id);
+        self.messages.insert(message.id, Entry {
+            message: message.clone(),
+            revisions: Vec::new()
+        });
+
+        while ids.len() > self.limit {
+            if let Some(id) = ids.pop_front() {
+                self.messages.remove(&id);
+            }
+        }
+    }
+    /// Remove a message, returning it if it was known
+    pub fn remove(&mut self, id: usize) -> Option<Message> {
+        let entry = self.messages.remove(&id)?;
+        if let Some(ids) = self.channels.get_mut(&entry.message.channel) {
+            if let Ok(index) = ids.binary_search(&id) {
+                ids.remove(index);
+            }
+        }
+        Some(entry.message)
+    }
+    /// Remove all messages in a channel
+    pub fn remove_channel(&mut self, channel: usize) {
+        if let Some(ids) = self.channels.remove(&channel) {
+            for id in ids {
+                self.messages.remove(&id);
+            }
+        }
+    }
+
+    /// Get a message by id
+    pub fn message(&self, id: usize) -> Option<&Message> {
+        self.messages.get(&id).map(|entry| &entry.message)
+    }
+    /// Get the channel a message was sent in.
+    /// Useful to resolve `MessageDeleteReceive` before the message is removed.
+    pub fn message_channel(&self, id: usize) -> Option<usize> {
+        self.message(id).map(|message| message.channel)
+    }
+    /// Get the previous versions of a message, oldest first
+    pub fn revisions(&self, id: usize) -> &[Message] {
+        self.messages.get(&id).map(|entry| &*entry.revisions).unwrap_or(&[])
+    }
+    /// Get all remembered messages in a channel, oldest first
+    pub fn messages<'a>(&'a self, channel: usize) -> impl DoubleEndedIterator<Item = &'a Message> + 'a {
+        self.channels.get(&channel)
+            .into_iter()
+            .flat_map(|ids| ids.iter())
+            .filter_map(move |id| self.message(*id))
+    }
+    /// Get the most recent remembered message in a channel
+    pub fn last_message(&self, channel: usize) -> Option<&Message> {
+        self.channels.get(&channel)
+            .and_then(|ids| ids.back())
+            .and_then(|id| self.message(*id))
+    }
+}
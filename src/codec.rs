@@ -0,0 +1,57 @@
+use crate::common::{self, Packet};
+use bytes::{Bytes, BytesMut};
+use failure::Error;
+use tokio_util::codec::{Decoder, Encoder, LengthDelimitedCodec};
+
+/// Frames packets the same way as `common::read` and `common::write`,
+/// for use with `tokio_util::codec::Framed`
+pub struct Codec {
+    inner: LengthDelimitedCodec
+}
+
+impl Default for Codec {
+    fn default() -> Self {
+        Codec {
+            inner: LengthDelimitedCodec::builder()
+                .length_field_length(2)
+                .max_frame_length(u16::MAX as usize)
+                .new_codec()
+        }
+    }
+}
+impl Codec {
+    /// Create a new codec
+    pub fn new() -> Self {
+        Codec::default()
+    }
+}
+
+impl Decoder for Codec {
+    type Item = Packet;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Packet>, Error> {
+        match self.inner.decode(src)? {
+            Some(buf) => Ok(Some(common::deserialize(&buf)?)),
+            None => Ok(None)
+        }
+    }
+}
+impl<'a> Encoder<&'a Packet> for Codec {
+    type Error = Error;
+
+    fn encode(&mut self, packet: &'a Packet, dst: &mut BytesMut) -> Result<(), Error> {
+        let buf = common::serialize(packet)?;
+        if buf.len() > u16::MAX as usize {
+            return Err(common::Error::PacketTooBigError.into());
+        }
+        Ok(self.inner.encode(Bytes::from(buf), dst)?)
+    }
+}
+impl Encoder<Packet> for Codec {
+    type Error = Error;
+
+    fn encode(&mut self, packet: Packet, dst: &mut BytesMut) -> Result<(), Error> {
+        self.encode(&packet, dst)
+    }
+}
@@ -0,0 +1,308 @@
+use crate::common::{self, Packet, User};
+use crate::get_effective_mode;
+use crate::mode::Mode;
+use crate::state::State;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error as StdError;
+use std::fmt;
+use std::str::FromStr;
+
+/// Why a command failed. It's replied to the author.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommandError {
+    /// The arguments were wrong. The command's usage is replied along with this.
+    Usage(String),
+    /// The author isn't allowed to use the command
+    Denied,
+    /// Anything else
+    Failed(String)
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CommandError::Usage(ref message) | CommandError::Failed(ref message) => write!(f, "{}", message),
+            CommandError::Denied => write!(f, "you're not allowed to use this command")
+        }
+    }
+}
+impl StdError for CommandError {}
+
+/// What a command handler gets to work with
+pub struct CommandContext<'a> {
+    /// The user who sent the command
+    pub author: usize,
+    /// The state, already updated with everything received before the command
+    pub state: &'a State,
+    args: &'a [String],
+    next: usize,
+    response: CommandResponse
+}
+
+impl<'a> CommandContext<'a> {
+    /// All arguments, not including the command name
+    pub fn args(&self) -> &[String] {
+        self.args
+    }
+    /// Parse the next argument, failing with a usage error if it's missing or invalid.
+    /// `name` is what the error calls it.
+    pub fn arg<T: FromStr>(&mut self, name: &str) -> Result<T, CommandError> {
+        self.optional_arg(name)?.ok_or_else(|| CommandError::Usage(format!("missing {}", name)))
+    }
+    /// Parse the next argument if there is one, failing with a usage error if it's invalid
+    pub fn optional_arg<T: FromStr>(&mut self, name: &str) -> Result<Option<T>, CommandError> {
+        let arg = match self.args.get(self.next) {
+            Some(arg) => arg,
+            None => return Ok(None)
+        };
+        self.next += 1;
+        arg.parse()
+            .map(Some)
+            .map_err(|_| CommandError::Usage(format!("invalid {}: {}", name, arg)))
+    }
+    /// Take all remaining arguments, joined by spaces
+    pub fn rest(&mut self) -> String {
+        let rest = self.args.get(self.next..).unwrap_or_default().join(" ");
+        self.next = self.args.len();
+        rest
+    }
+
+    /// Send `text` to the author in a private channel
+    pub fn reply<S: Into<String>>(&mut self, text: S) {
+        self.response.replies.push(text.into());
+    }
+    /// Send any other packet once the command is done
+    pub fn send(&mut self, packet: Packet) {
+        self.response.packets.push(packet);
+    }
+}
+
+/// What running a command resulted in
+#[derive(Clone, Debug, Default)]
+pub struct CommandResponse {
+    /// Messages for the author
+    pub replies: Vec<String>,
+    /// Other packets to send
+    pub packets: Vec<Packet>
+}
+
+type Handler = Box<dyn Fn(&mut CommandContext) -> Result<(), CommandError> + Send + Sync>;
+type Check = Box<dyn Fn(&State, &User) -> bool + Send + Sync>;
+
+/// A command a `Router` can run
+pub struct BotCommand {
+    name: String,
+    usage: String,
+    description: String,
+    checks: Vec<Check>,
+    handler: Handler
+}
+
+impl BotCommand {
+    /// Create a command that runs `handler` when invoked as `name`
+    pub fn new<S, F>(name: S, handler: F) -> Self
+        where
+            S: Into<String>,
+            F: Fn(&mut CommandContext) -> Result<(), CommandError> + Send + Sync + 'static
+    {
+        BotCommand {
+            name: name.into(),
+            usage: String::new(),
+            description: String::new(),
+            checks: Vec::new(),
+            handler: Box::new(handler)
+        }
+    }
+    /// Describe the arguments for `help` and usage errors, like `<sides> [count]`
+    pub fn usage<S: Into<String>>(mut self, usage: S) -> Self {
+        self.usage = usage.into();
+        self
+    }
+    /// Describe what the command does for `help`
+    pub fn description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = description.into();
+        self
+    }
+    /// Only allow admins to use the command
+    pub fn require_admin(self) -> Self {
+        self.require(|_, user| user.admin)
+    }
+    /// Only allow users who have `mode` in `channel`, see `get_effective_mode`
+    pub fn require_mode(self, channel: usize, mode: Mode) -> Self {
+        self.require(move |state, user| {
+            state.channels.get(&channel)
+                .map(|channel| get_effective_mode(channel, user).contains(mode))
+                .unwrap_or(false)
+        })
+    }
+    /// Only allow users `check` accepts
+    pub fn require<F>(mut self, check: F) -> Self
+        where F: Fn(&State, &User) -> bool + Send + Sync + 'static
+    {
+        self.checks.push(Box::new(check));
+        self
+    }
+
+    /// Check if `author` may use the command. Unknown users only may if there are no requirements.
+    pub fn allowed(&self, state: &State, author: usize) -> bool {
+        match state.users.get(&author) {
+            Some(user) => self.checks.iter().all(|check| check(state, user)),
+            None => self.checks.is_empty()
+        }
+    }
+    fn usage_line(&self) -> String {
+        if self.usage.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} {}", self.name, self.usage)
+        }
+    }
+}
+
+/// Runs the `BotCommand` named by the first argument of each `CommandReceive`,
+/// without caring how packets are sent and received.
+/// Unless a command named `help` is added, `help` lists the commands the author may use.
+///
+/// Update the `State` with every packet first, then pass it to `feed` and send what it returns.
+/// Replies go to a private channel with the author, which is created if needed.
+/// If creating it fails, the replies waiting for it are dropped:
+/// call `send_failed` when a packet couldn't be sent.
+/// The server doesn't say which request an error is for, so any `Err` drops them too.
+#[derive(Default)]
+pub struct Router {
+    commands: BTreeMap<String, BotCommand>,
+    // replies waiting for a private channel to be created
+    pending: HashMap<usize, Vec<String>>
+}
+
+impl Router {
+    /// Create a router without any commands
+    pub fn new() -> Self {
+        Router::default()
+    }
+    /// Add `command`, replacing any with the same name
+    pub fn command(mut self, command: BotCommand) -> Self {
+        self.commands.insert(command.name.clone(), command);
+        self
+    }
+
+    /// Run the command `args` names on behalf of `author`.
+    /// Errors are turned into replies.
+    pub fn dispatch(&self, state: &State, author: usize, args: &[String]) -> CommandResponse {
+        let name = match args.first() {
+            Some(name) => name,
+            None => return CommandResponse { replies: vec![self.help(state, author)], packets: Vec::new() }
+        };
+        let command = match self.commands.get(name) {
+            Some(command) => command,
+            None if name == "help" => {
+                let reply = match args.get(1).and_then(|name| self.commands.get(name)) {
+                    Some(command) if command.allowed(state, author) => self.describe(command),
+                    _ => self.help(state, author)
+                };
+                return CommandResponse { replies: vec![reply], packets: Vec::new() };
+            },
+            None => return CommandResponse {
+                replies: vec![format!("unknown command {}, try help", name)],
+                packets: Vec::new()
+            }
+        };
+
+        let mut context = CommandContext {
+            author,
+            state,
+            args: &args[1..],
+            next: 0,
+            response: CommandResponse::default()
+        };
+        let result = if command.allowed(state, author) {
+            (command.handler)(&mut context)
+        } else {
+            Err(CommandError::Denied)
+        };
+        let mut response = context.response;
+        match result {
+            Ok(()) => (),
+            Err(CommandError::Usage(message)) =>
+                response.replies.push(format!("{}\nusage: {}", message, command.usage_line())),
+            Err(err) => response.replies.push(err.to_string())
+        }
+        response
+    }
+    /// Handle a packet, returning the packets to send
+    pub fn feed(&mut self, state: &State, packet: &Packet) -> Vec<Packet> {
+        match *packet {
+            Packet::CommandReceive(ref event) => {
+                let response = self.dispatch(state, event.author, &event.args);
+                let mut packets = response.packets;
+                for reply in response.replies {
+                    packets.extend(self.reply(state, event.author, reply));
+                }
+                packets
+            },
+            // a requested private channel might have arrived
+            Packet::ChannelReceive(_) | Packet::UserReceive(_) => {
+                let ready: Vec<usize> = self.pending.keys()
+                    .filter(|user| state.dm_channel(**user).is_some())
+                    .cloned()
+                    .collect();
+                let mut packets = Vec::new();
+                for user in ready {
+                    for reply in self.pending.remove(&user).unwrap_or_default() {
+                        packets.extend(self.reply(state, user, reply));
+                    }
+                }
+                packets
+            },
+            Packet::Err(_) => {
+                self.pending.clear();
+                Vec::new()
+            },
+            _ => Vec::new()
+        }
+    }
+    /// Tell the router that sending a packet `feed` returned failed,
+    /// so replies waiting for a private channel it requested are dropped
+    pub fn send_failed(&mut self, packet: &Packet) {
+        if let Packet::ChannelCreate(common::ChannelCreate { recipient: Some(user), .. }) = *packet {
+            self.pending.remove(&user);
+        }
+    }
+
+    fn reply(&mut self, state: &State, user: usize, text: String) -> Option<Packet> {
+        if let Some(channel) = state.dm_channel(user) {
+            return Some(Packet::MessageCreate(common::MessageCreate {
+                channel: channel.id,
+                text: text.into_bytes()
+            }));
+        }
+        let pending = self.pending.entry(user).or_default();
+        pending.push(text);
+        if pending.len() > 1 {
+            // already requested
+            return None;
+        }
+        Some(Packet::ChannelCreate(common::ChannelCreate {
+            recipient: Some(user),
+            ..Default::default()
+        }))
+    }
+    fn help(&self, state: &State, author: usize) -> String {
+        let mut help = String::from("Commands:");
+        for command in self.commands.values().filter(|command| command.allowed(state, author)) {
+            help.push('\n');
+            help.push_str(&self.describe(command));
+        }
+        if !self.commands.contains_key("help") {
+            help.push_str("\nhelp [command] - List commands, or describe one");
+        }
+        help
+    }
+    fn describe(&self, command: &BotCommand) -> String {
+        if command.description.is_empty() {
+            command.usage_line()
+        } else {
+            format!("{} - {}", command.usage_line(), command.description)
+        }
+    }
+}
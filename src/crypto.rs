@@ -0,0 +1,41 @@
+#[cfg(not(feature = "openssl"))] use base64::Engine;
+#[cfg(not(feature = "openssl"))] use base64::engine::general_purpose::STANDARD as BASE64;
+#[cfg(all(feature = "server", not(feature = "openssl")))] use ring::rand::{SecureRandom, SystemRandom};
+
+#[cfg(feature = "openssl")]
+pub(crate) fn sha256(input: &[u8]) -> [u8; 32] {
+    openssl::sha::sha256(input)
+}
+#[cfg(not(feature = "openssl"))]
+pub(crate) fn sha256(input: &[u8]) -> [u8; 32] {
+    let mut digest = [0; 32];
+    digest.copy_from_slice(ring::digest::digest(&ring::digest::SHA256, input).as_ref());
+    digest
+}
+#[cfg(feature = "openssl")]
+pub(crate) fn base64_encode(input: &[u8]) -> String {
+    openssl::base64::encode_block(input)
+}
+#[cfg(not(feature = "openssl"))]
+pub(crate) fn base64_encode(input: &[u8]) -> String {
+    BASE64.encode(input)
+}
+#[cfg(feature = "openssl")]
+pub(crate) fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    if input.is_empty() {
+        return Some(Vec::new());
+    }
+    openssl::base64::decode_block(input).ok()
+}
+#[cfg(not(feature = "openssl"))]
+pub(crate) fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    BASE64.decode(input).ok()
+}
+#[cfg(all(feature = "server", feature = "openssl"))]
+pub(crate) fn random_bytes(buf: &mut [u8]) {
+    openssl::rand::rand_bytes(buf).expect("failed to generate random bytes");
+}
+#[cfg(all(feature = "server", not(feature = "openssl")))]
+pub(crate) fn random_bytes(buf: &mut [u8]) {
+    SystemRandom::new().fill(buf).expect("failed to generate random bytes");
+}
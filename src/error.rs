@@ -0,0 +1,123 @@
+use crate::common::{self, Packet};
+use std::error::Error as StdError;
+use std::fmt;
+
+/// An error the server responded with.
+/// Converts losslessly to and from the `ERR_*` codes in `common`.
+///
+/// The protocol answers requests asynchronously, so the typed request methods only fail if sending does.
+/// Errors arrive later as `Packet::Err`. `read_checked` on either session returns them as this type,
+/// `login` waits for them, and `check` does the same for packets from the async `Stream`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ServerError {
+    AlreadyExists,
+    LimitReached,
+    LoginBanned,
+    LoginBot,
+    LoginInvalid,
+    MaxConnPerIp,
+    MissingField,
+    MissingPermission,
+    SelfPm,
+    UnknownBot,
+    UnknownChannel,
+    UnknownMessage,
+    UnknownUser,
+    /// An error code this version of the library doesn't know about
+    Unknown(u8)
+}
+
+impl ServerError {
+    /// Returns the error contained in `packet`, if it's a `Packet::Err`
+    pub fn from_packet(packet: &Packet) -> Option<ServerError> {
+        match *packet {
+            Packet::Err(code) => Some(ServerError::from(code)),
+            _ => None
+        }
+    }
+    /// Returns `packet` unless it's a `Packet::Err`, which is returned as an error instead
+    pub fn check(packet: Packet) -> Result<Packet, ServerError> {
+        match ServerError::from_packet(&packet) {
+            Some(err) => Err(err),
+            None => Ok(packet)
+        }
+    }
+    /// Returns the wire code of this error
+    pub fn code(&self) -> u8 {
+        u8::from(*self)
+    }
+}
+
+impl From<u8> for ServerError {
+    fn from(code: u8) -> Self {
+        match code {
+            common::ERR_ALREADY_EXISTS     => ServerError::AlreadyExists,
+            common::ERR_LIMIT_REACHED      => ServerError::LimitReached,
+            common::ERR_LOGIN_BANNED       => ServerError::LoginBanned,
+            common::ERR_LOGIN_BOT          => ServerError::LoginBot,
+            common::ERR_LOGIN_INVALID      => ServerError::LoginInvalid,
+            common::ERR_MAX_CONN_PER_IP    => ServerError::MaxConnPerIp,
+            common::ERR_MISSING_FIELD      => ServerError::MissingField,
+            common::ERR_MISSING_PERMISSION => ServerError::MissingPermission,
+            common::ERR_SELF_PM            => ServerError::SelfPm,
+            common::ERR_UNKNOWN_BOT        => ServerError::UnknownBot,
+            common::ERR_UNKNOWN_CHANNEL    => ServerError::UnknownChannel,
+            common::ERR_UNKNOWN_MESSAGE    => ServerError::UnknownMessage,
+            common::ERR_UNKNOWN_USER       => ServerError::UnknownUser,
+            code => ServerError::Unknown(code)
+        }
+    }
+}
+impl From<ServerError> for u8 {
+    fn from(err: ServerError) -> Self {
+        match err {
+            ServerError::AlreadyExists     => common::ERR_ALREADY_EXISTS,
+            ServerError::LimitReached      => common::ERR_LIMIT_REACHED,
+            ServerError::LoginBanned       => common::ERR_LOGIN_BANNED,
+            ServerError::LoginBot          => common::ERR_LOGIN_BOT,
+            ServerError::LoginInvalid      => common::ERR_LOGIN_INVALID,
+            ServerError::MaxConnPerIp      => common::ERR_MAX_CONN_PER_IP,
+            ServerError::MissingField      => common::ERR_MISSING_FIELD,
+            ServerError::MissingPermission => common::ERR_MISSING_PERMISSION,
+            ServerError::SelfPm            => common::ERR_SELF_PM,
+            ServerError::UnknownBot        => common::ERR_UNKNOWN_BOT,
+            ServerError::UnknownChannel    => common::ERR_UNKNOWN_CHANNEL,
+            ServerError::UnknownMessage    => common::ERR_UNKNOWN_MESSAGE,
+            ServerError::UnknownUser       => common::ERR_UNKNOWN_USER,
+            ServerError::Unknown(code)     => code
+        }
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ServerError::AlreadyExists     => write!(f, "already exists"),
+            ServerError::LimitReached      => write!(f, "limit reached"),
+            ServerError::LoginBanned       => write!(f, "banned"),
+            ServerError::LoginBot          => write!(f, "wrong account type (bot or not)"),
+            ServerError::LoginInvalid      => write!(f, "invalid credentials"),
+            ServerError::MaxConnPerIp      => write!(f, "too many connections from this IP"),
+            ServerError::MissingField      => write!(f, "missing field"),
+            ServerError::MissingPermission => write!(f, "missing permission"),
+            ServerError::SelfPm            => write!(f, "can't create a private channel with yourself"),
+            ServerError::UnknownBot        => write!(f, "unknown bot"),
+            ServerError::UnknownChannel    => write!(f, "unknown channel"),
+            ServerError::UnknownMessage    => write!(f, "unknown message"),
+            ServerError::UnknownUser       => write!(f, "unknown user"),
+            ServerError::Unknown(code)     => write!(f, "unknown error code {}", code)
+        }
+    }
+}
+impl StdError for ServerError {}
+
+/// The server rate limited a request for the specified amount of seconds
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RateLimited(pub u64);
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rate limited for {} seconds", self.0)
+    }
+}
+impl StdError for RateLimited {}
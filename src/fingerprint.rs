@@ -0,0 +1,142 @@
+use crate::builder::{SessionBuilder, Verification};
+use crate::crypto::{base64_decode, base64_encode, sha256};
+#[cfg(feature = "openssl")] use openssl::x509::X509;
+#[cfg(not(feature = "openssl"))] use rustls::pki_types::CertificateDer;
+#[cfg(not(feature = "openssl"))] use std::convert::TryFrom;
+#[cfg(not(feature = "openssl"))] use webpki::EndEntityCert;
+use failure::Error;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::net::ToSocketAddrs;
+use std::str::{self, FromStr};
+
+/// Returned when parsing something that isn't a fingerprint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidFingerprint;
+
+impl fmt::Display for InvalidFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid fingerprint: expected 32 bytes of hex or base64")
+    }
+}
+impl StdError for InvalidFingerprint {}
+
+/// The SHA-256 hash of a server's public key, which is what `Session::new` pins.
+/// It's taken of the key's PEM encoding, so it matches
+/// `openssl pkey -pubin -outform pem | sha256sum`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub [u8; 32]);
+
+impl Fingerprint {
+    /// Hash a DER encoded public key (SubjectPublicKeyInfo)
+    pub fn from_public_key_der(der: &[u8]) -> Self {
+        Fingerprint(sha256(public_key_to_pem(der).as_bytes()))
+    }
+    /// Hash a PEM encoded public key.
+    /// It's re-encoded first, so line endings and surrounding text don't matter.
+    pub fn from_public_key_pem(pem: &[u8]) -> Result<Self, Error> {
+        Ok(Fingerprint::from_public_key_der(&pem_decode(pem, "PUBLIC KEY")?))
+    }
+    /// Hash the public key of a DER encoded X509 certificate
+    pub fn from_cert_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(Fingerprint::from_public_key_der(&cert_public_key(der)?))
+    }
+    /// Hash the public key of a PEM encoded X509 certificate
+    pub fn from_cert_pem(pem: &[u8]) -> Result<Self, Error> {
+        Fingerprint::from_cert_der(&pem_decode(pem, "CERTIFICATE")?)
+    }
+
+    /// Returns true if `hash` is this fingerprint in any format `from_str` accepts
+    pub fn matches(&self, hash: &str) -> bool {
+        hash.parse::<Fingerprint>().map(|hash| hash == *self).unwrap_or(false)
+    }
+    /// Uppercase hex, like `Session::new` takes. Same as `to_string`.
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02X}", byte)).collect()
+    }
+    /// Uppercase hex with a colon between each byte
+    pub fn to_colon_hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(":")
+    }
+    /// Standard padded base64
+    pub fn to_base64(&self) -> String {
+        base64_encode(&self.0)
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+impl FromStr for Fingerprint {
+    type Err = InvalidFingerprint;
+
+    /// Parse hex of either case, with or without colons, or base64
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let hex = input.replace(':', "");
+        let bytes = if hex.len() == 64 && hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            (0..32).map(|i| u8::from_str_radix(&hex[i*2..i*2+2], 16).unwrap()).collect()
+        } else {
+            base64_decode(input).ok_or(InvalidFingerprint)?
+        };
+        let mut digest = [0; 32];
+        if bytes.len() != digest.len() {
+            return Err(InvalidFingerprint);
+        }
+        digest.copy_from_slice(&bytes);
+        Ok(Fingerprint(digest))
+    }
+}
+
+/// Connect to `addr` without verifying it and get the fingerprint of its key.
+/// Use `SessionBuilder` with `Verification::None` to also set a server name.
+pub fn fetch<A: ToSocketAddrs>(addr: A) -> Result<Fingerprint, Error> {
+    let session = SessionBuilder::new().verification(Verification::None).connect(addr)?;
+    Ok(session.peer_fingerprint().ok_or_else(no_key)?)
+}
+pub(crate) fn no_key() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "server has no public key")
+}
+
+/// Encodes a DER SubjectPublicKeyInfo the same way as openssl's `public_key_to_pem`
+fn public_key_to_pem(der: &[u8]) -> String {
+    let encoded = base64_encode(der);
+    let mut pem = String::from("-----BEGIN PUBLIC KEY-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        // base64 is always ascii
+        pem.push_str(str::from_utf8(line).unwrap());
+        pem.push('\n');
+    }
+    pem.push_str("-----END PUBLIC KEY-----\n");
+    pem
+}
+/// Get the DER contents of the first `label` block in `pem`
+fn pem_decode(pem: &[u8], label: &str) -> Result<Vec<u8>, Error> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("no valid {} in PEM", label));
+    let pem = str::from_utf8(pem).map_err(|_| invalid())?;
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+
+    let body: String = pem.lines()
+        .map(str::trim)
+        .skip_while(|line| *line != begin)
+        .skip(1)
+        .take_while(|line| *line != end)
+        .collect();
+    base64_decode(&body).filter(|der| !der.is_empty()).ok_or_else(|| invalid().into())
+}
+
+#[cfg(feature = "openssl")]
+fn cert_public_key(der: &[u8]) -> Result<Vec<u8>, Error> {
+    Ok(X509::from_der(der)?.public_key()?.public_key_to_der()?)
+}
+#[cfg(not(feature = "openssl"))]
+fn cert_public_key(der: &[u8]) -> Result<Vec<u8>, Error> {
+    let der = CertificateDer::from(der);
+    let cert = EndEntityCert::try_from(&der)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid certificate"))?;
+    Ok(cert.subject_public_key_info().as_ref().to_vec())
+}
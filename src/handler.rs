@@ -0,0 +1,181 @@
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] use crate::asynchronous;
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] use tokio::io::{AsyncRead, AsyncWrite};
+use crate::blocking::Session;
+use crate::common::{self, Channel, LoginSuccess, Message, Packet, User};
+use crate::error::ServerError;
+use crate::state::{Change, State};
+use failure::Error;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+/// What an event handler gets to work with
+pub struct EventContext<'a> {
+    /// The state, already updated with the packet
+    pub state: &'a State,
+    /// The channel the event happened in, if it's known
+    pub channel: Option<&'a Channel>,
+    /// The user who caused the event, if it's known
+    pub author: Option<&'a User>,
+    outbox: &'a mut Vec<Packet>
+}
+
+impl<'a> EventContext<'a> {
+    /// Send `packet` once the handler returns
+    pub fn send(&mut self, packet: Packet) {
+        self.outbox.push(packet);
+    }
+    /// Send a message to the channel the event happened in.
+    /// Returns false if there is no known channel.
+    pub fn reply<S: Into<Vec<u8>>>(&mut self, text: S) -> bool {
+        let channel = match self.channel {
+            Some(channel) => channel.id,
+            None => return false
+        };
+        self.send(Packet::MessageCreate(common::MessageCreate {
+            channel,
+            text: text.into()
+        }));
+        true
+    }
+}
+
+/// Callbacks for each kind of packet the server sends, see `Driver`.
+/// Every method does nothing by default, so only implement the ones you need.
+#[allow(unused_variables)]
+pub trait EventHandler {
+    /// Called for every packet, before the more specific method
+    fn on_packet(&mut self, ctx: &mut EventContext, packet: &Packet) {}
+    /// Logged in successfully
+    fn on_login(&mut self, ctx: &mut EventContext, event: &LoginSuccess) {}
+    /// A new message was sent
+    fn on_message(&mut self, ctx: &mut EventContext, message: &Message) {}
+    /// A message was edited, as told by its `timestamp_edit`.
+    /// The protocol sends history the same way, so an edited message in requested history
+    /// is reported as an edit too, unless the state's message cache already has it unchanged.
+    fn on_message_edit(&mut self, ctx: &mut EventContext, message: &Message) {}
+    /// An old message was received, usually because history was requested
+    fn on_message_history(&mut self, ctx: &mut EventContext, message: &Message) {}
+    /// A message was deleted. The message is only known if the state's message cache had it.
+    fn on_message_delete(&mut self, ctx: &mut EventContext, id: usize, message: Option<&Message>) {}
+    /// A channel was created, or sent for the first time
+    fn on_channel_create(&mut self, ctx: &mut EventContext, channel: &Channel) {}
+    /// A known channel was changed
+    fn on_channel_update(&mut self, ctx: &mut EventContext, channel: &Channel) {}
+    /// A channel was deleted
+    fn on_channel_delete(&mut self, ctx: &mut EventContext, channel: &Channel) {}
+    /// A user was created, changed or sent for the first time
+    fn on_user_update(&mut self, ctx: &mut EventContext, user: &User) {}
+    /// Called after `on_channel_*` and `on_user_update` for each change, such as a rename or ban
+    fn on_change(&mut self, ctx: &mut EventContext, change: &Change) {}
+    /// Somebody started typing in `ctx.channel`
+    fn on_typing(&mut self, ctx: &mut EventContext) {}
+    /// A user sent you a command. Only bots receive these.
+    fn on_command(&mut self, ctx: &mut EventContext, args: &[String]) {}
+    /// The server refused something you sent
+    fn on_error(&mut self, ctx: &mut EventContext, error: ServerError) {}
+    /// The server dropped something you sent, try again after `delay`
+    fn on_rate_limited(&mut self, ctx: &mut EventContext, delay: Duration) {}
+}
+
+/// Updates the `State` with each packet, then calls the matching `EventHandler` method,
+/// without caring how packets are sent and received.
+/// Pass every received packet to `handle` and send what it returns, or let `run` do that.
+pub struct Driver<H: EventHandler> {
+    pub state: State,
+    pub handler: H
+}
+
+impl<H: EventHandler> Driver<H> {
+    /// Create a driver with empty state
+    pub fn new(handler: H) -> Self {
+        Driver::with_state(State::new(), handler)
+    }
+    /// Create a driver using `state`, such as one with a message cache
+    pub fn with_state(state: State, handler: H) -> Self {
+        Driver { state, handler }
+    }
+
+    /// Handle a packet, returning the packets the handler wants to send
+    pub fn handle(&mut self, packet: &Packet) -> Vec<Packet> {
+        // whatever the update removes or replaces has to be looked at first
+        let (deleted, known) = match *packet {
+            Packet::MessageDeleteReceive(ref event) => (self.state.message(event.id).cloned(), false),
+            Packet::MessageReceive(ref event) => {
+                let new = &event.inner;
+                // an edited message that's already cached like this is just history
+                let edited = new.timestamp_edit.is_some() && self.state.message(new.id)
+                    .map(|old| old.timestamp_edit != new.timestamp_edit || old.text != new.text)
+                    .unwrap_or(true);
+                (None, edited)
+            },
+            Packet::ChannelReceive(ref event) => (None, self.state.channels.contains_key(&event.inner.id)),
+            _ => (None, false)
+        };
+        let changes = self.state.update_with_changes(packet);
+
+        let state = &self.state;
+        let mut outbox = Vec::new();
+        let (channel, author) = match *packet {
+            Packet::ChannelDeleteReceive(ref event) => (Some(&event.inner), None),
+            Packet::ChannelReceive(ref event) => (state.channels.get(&event.inner.id), None),
+            Packet::CommandReceive(ref event) => (None, state.users.get(&event.author)),
+            Packet::MessageReceive(ref event) => (state.channels.get(&event.inner.channel), state.users.get(&event.inner.author)),
+            Packet::MessageDeleteReceive(_) => match deleted {
+                Some(ref message) => (state.channels.get(&message.channel), state.users.get(&message.author)),
+                None => (None, None)
+            },
+            Packet::TypingReceive(ref event) => (state.channels.get(&event.channel), state.users.get(&event.author)),
+            Packet::UserReceive(ref event) => (None, state.users.get(&event.inner.id)),
+            _ => (None, None)
+        };
+        let mut ctx = EventContext {
+            state,
+            channel,
+            author,
+            outbox: &mut outbox
+        };
+
+        let handler = &mut self.handler;
+        handler.on_packet(&mut ctx, packet);
+        match *packet {
+            Packet::Err(code) => handler.on_error(&mut ctx, ServerError::from(code)),
+            Packet::RateLimited(seconds) => handler.on_rate_limited(&mut ctx, Duration::from_secs(seconds)),
+            Packet::ChannelDeleteReceive(ref event) => handler.on_channel_delete(&mut ctx, &event.inner),
+            Packet::ChannelReceive(ref event) if known => handler.on_channel_update(&mut ctx, &event.inner),
+            Packet::ChannelReceive(ref event) => handler.on_channel_create(&mut ctx, &event.inner),
+            Packet::CommandReceive(ref event) => handler.on_command(&mut ctx, &event.args),
+            Packet::LoginSuccess(ref event) => handler.on_login(&mut ctx, event),
+            Packet::MessageDeleteReceive(ref event) => handler.on_message_delete(&mut ctx, event.id, deleted.as_ref()),
+            Packet::MessageReceive(ref event) if event.new => handler.on_message(&mut ctx, &event.inner),
+            Packet::MessageReceive(ref event) if known => handler.on_message_edit(&mut ctx, &event.inner),
+            Packet::MessageReceive(ref event) => handler.on_message_history(&mut ctx, &event.inner),
+            Packet::TypingReceive(_) => handler.on_typing(&mut ctx),
+            Packet::UserReceive(ref event) => handler.on_user_update(&mut ctx, &event.inner),
+            _ => ()
+        }
+        for change in &changes {
+            handler.on_change(&mut ctx, change);
+        }
+        outbox
+    }
+
+    /// Read packets from `session` and handle them until reading or writing fails
+    pub fn run<T: Read + Write>(&mut self, session: &mut Session<T>) -> Result<(), Error> {
+        loop {
+            let packet = session.read()?;
+            for packet in self.handle(&packet) {
+                session.write(&packet)?;
+            }
+        }
+    }
+    /// Read packets from `session` and handle them until reading or writing fails
+    #[cfg(any(feature = "tokio", feature = "tokio-rustls"))]
+    pub async fn run_async<T: AsyncRead + AsyncWrite + Unpin>(&mut self, session: &mut asynchronous::Session<T>) -> Result<(), Error> {
+        loop {
+            let packet = session.read().await?;
+            for packet in self.handle(&packet) {
+                session.write(&packet).await?;
+            }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] use crate::asynchronous;
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] use futures::stream::{self, Stream};
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] use tokio::io::{AsyncRead, AsyncWrite};
+use crate::blocking::{Session, Stream as TlsStream};
+use crate::common::{self, Message, Packet};
+use crate::error::ServerError;
+use failure::Error;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::iter::FusedIterator;
+use std::thread;
+use std::time::Duration;
+
+/// Which way to walk through history
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Newest message first
+    Backwards,
+    /// Oldest message first
+    Forwards
+}
+
+/// What `HistoryPager::feed` did with a packet
+#[derive(Clone, Debug)]
+pub enum Feed {
+    /// The packet wasn't part of the history, handle it like usual
+    Ignored,
+    /// The packet was a message in the current page
+    Consumed,
+    /// The current page is complete, in the requested order.
+    /// Send `request` again for the next one.
+    Page(Vec<Message>),
+    /// The request was dropped. Wait this long, then send `request` again.
+    RateLimited(Duration),
+    /// The server refused to list messages. No further pages will be requested.
+    Failed(ServerError)
+}
+
+/// Walks through a channel's history one page at a time,
+/// without caring how packets are sent and received.
+///
+/// Send `request()`, pass every received packet to `feed` until it returns a page,
+/// and repeat until `request()` returns `None`.
+///
+/// The server doesn't say which request a response belongs to.
+/// While a page is requested, `Err`, `RateLimited` and edited messages in the channel
+/// are taken as part of the response, so avoid sending other requests until the page arrives.
+/// Outside of that, `feed` ignores them.
+#[derive(Clone, Debug)]
+pub struct HistoryPager {
+    channel: usize,
+    direction: Direction,
+    page_size: usize,
+    cursor: Option<usize>,
+    page: Vec<Message>,
+    requested: bool,
+    done: bool
+}
+
+impl HistoryPager {
+    /// Create a new pager for `channel`, requesting `page_size` messages at a time.
+    /// Backwards starts at the newest message, forwards at the oldest.
+    /// `page_size` is clamped between 1 and `common::LIMIT_BULK`, the most the server accepts.
+    pub fn new(channel: usize, direction: Direction, page_size: usize) -> Self {
+        HistoryPager {
+            channel,
+            direction,
+            page_size: page_size.clamp(1, common::LIMIT_BULK),
+            cursor: None,
+            page: Vec::new(),
+            requested: false,
+            done: false
+        }
+    }
+    /// Start after (or before, when going backwards) message `id`, not including it
+    pub fn starting_at(mut self, id: usize) -> Self {
+        self.cursor = Some(id);
+        self
+    }
+    /// Returns true once the whole history has been walked through
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Get the packet that requests the next page, or `None` if there are no more pages.
+    /// From now on, `feed` expects the response.
+    pub fn request(&mut self) -> Option<Packet> {
+        if self.done {
+            return None;
+        }
+        self.requested = true;
+        let (before, after) = match (self.direction, self.cursor) {
+            (Direction::Backwards, cursor) => (cursor, None),
+            (Direction::Forwards, Some(cursor)) => (None, Some(cursor)),
+            // the oldest message can't be requested directly,
+            // but nothing is older than message 0
+            (Direction::Forwards, None) => (None, Some(0))
+        };
+        Some(Packet::MessageList(common::MessageList {
+            after,
+            before,
+            channel: self.channel,
+            limit: self.page_size
+        }))
+    }
+
+    /// Handle a packet received after sending `request`
+    pub fn feed(&mut self, packet: &Packet) -> Feed {
+        if !self.requested {
+            return Feed::Ignored;
+        }
+        match *packet {
+            Packet::MessageReceive(ref event)
+                    if !event.new && event.inner.channel == self.channel && self.in_range(event.inner.id) => {
+                self.page.push(event.inner.clone());
+                Feed::Consumed
+            },
+            Packet::MessageListReceived => {
+                self.requested = false;
+                let mut page = ::std::mem::take(&mut self.page);
+                page.sort_by_key(|message| message.id);
+                page.dedup_by_key(|message| message.id);
+                if self.direction == Direction::Backwards {
+                    page.reverse();
+                }
+
+                // a page that isn't full is the last one
+                if page.len() < self.page_size {
+                    self.done = true;
+                }
+                if let Some(message) = page.last() {
+                    self.cursor = Some(message.id);
+                }
+                Feed::Page(page)
+            },
+            Packet::RateLimited(seconds) => {
+                self.requested = false;
+                self.page.clear();
+                Feed::RateLimited(Duration::from_secs(seconds))
+            },
+            Packet::Err(code) => {
+                self.requested = false;
+                self.page.clear();
+                self.done = true;
+                Feed::Failed(ServerError::from(code))
+            },
+            _ => Feed::Ignored
+        }
+    }
+    fn in_range(&self, id: usize) -> bool {
+        match (self.direction, self.cursor) {
+            (_, None) => true,
+            (Direction::Backwards, Some(cursor)) => id < cursor,
+            (Direction::Forwards, Some(cursor)) => id > cursor
+        }
+    }
+}
+
+/// An iterator over a channel's history, see `Session::history`.
+/// Packets unrelated to the history are buffered and returned by `Session::read` later.
+/// It ends after returning an error.
+pub struct History<'a, T = TlsStream> {
+    session: &'a mut Session<T>,
+    pager: HistoryPager,
+    page: VecDeque<Message>,
+    failed: bool
+}
+impl<'a, T: Read + Write> History<'a, T> {
+    /// Create a new history iterator using `pager`
+    pub fn new(session: &'a mut Session<T>, pager: HistoryPager) -> Self {
+        History {
+            session,
+            pager,
+            page: VecDeque::new(),
+            failed: false
+        }
+    }
+    fn next_page(&mut self) -> Result<(), Error> {
+        while let Some(request) = self.pager.request() {
+            self.session.write(&request)?;
+            loop {
+                let packet = self.session.read_unbuffered()?;
+                match self.pager.feed(&packet) {
+                    Feed::Ignored => self.session.buffer_packet(packet),
+                    Feed::Consumed => (),
+                    Feed::Page(page) => {
+                        self.page.extend(page);
+                        return Ok(());
+                    },
+                    Feed::RateLimited(delay) => {
+                        thread::sleep(delay);
+                        break;
+                    },
+                    Feed::Failed(err) => return Err(err.into())
+                }
+            }
+        }
+        Ok(())
+    }
+}
+impl<'a, T: Read + Write> Iterator for History<'a, T> {
+    type Item = Result<Message, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        if self.page.is_empty() {
+            if let Err(err) = self.next_page() {
+                // the error is only returned once
+                self.failed = true;
+                return Some(Err(err));
+            }
+        }
+        self.page.pop_front().map(Ok)
+    }
+}
+impl<'a, T: Read + Write> FusedIterator for History<'a, T> {}
+
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))]
+/// A stream of a channel's history, see `asynchronous::Session::history`.
+/// Packets unrelated to the history are buffered and returned by `Session::read` later.
+pub fn history_stream<T>(session: &mut asynchronous::Session<T>, pager: HistoryPager) -> impl Stream<Item = Result<Message, Error>> + '_
+    where T: AsyncRead + AsyncWrite + Unpin
+{
+    stream::unfold(Some((session, pager, VecDeque::new())), |state| async move {
+        let (session, mut pager, mut page) = state?;
+        loop {
+            if let Some(message) = page.pop_front() {
+                return Some((Ok(message), Some((session, pager, page))));
+            }
+            let request = pager.request()?;
+            if let Err(err) = session.write(&request).await {
+                return Some((Err(err), None));
+            }
+            loop {
+                let packet = match session.read_unbuffered().await {
+                    Ok(packet) => packet,
+                    Err(err) => return Some((Err(err), None))
+                };
+                match pager.feed(&packet) {
+                    Feed::Ignored => session.buffer_packet(packet),
+                    Feed::Consumed => (),
+                    Feed::Page(new) => {
+                        page.extend(new);
+                        break;
+                    },
+                    Feed::RateLimited(delay) => {
+                        tokio::time::sleep(delay).await;
+                        break;
+                    },
+                    Feed::Failed(err) => return Some((Err(err.into()), None))
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,135 @@
+use crate::common::{self, Message, Packet};
+use crate::error::ServerError;
+use crate::state::State;
+use crate::typing::{Clock, SystemClock};
+use std::time::{Duration, Instant};
+
+/// A command sent to a bot
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invocation {
+    /// Identifies the invocation in `InvocationFeed`
+    pub id: usize,
+    /// The bot the command was sent to
+    pub bot: usize,
+    /// The command and its arguments
+    pub args: Vec<String>,
+    /// When the command was sent
+    pub sent: Instant,
+    /// True once the bot has replied at least once
+    pub replied: bool
+}
+
+/// What `Invocations::feed` did with a packet
+#[derive(Clone, Debug)]
+pub enum InvocationFeed {
+    /// The packet wasn't related to any invocation, handle it like usual
+    Ignored,
+    /// The bot sent a message after invocation `id`. A bot may reply more than once.
+    Reply { id: usize, message: Message },
+    /// The server refused invocation `id`, usually with `ServerError::UnknownBot`.
+    /// It's no longer tracked.
+    Failed { id: usize, error: ServerError }
+}
+
+/// Follows up on commands sent to bots, without caring how packets are sent and received.
+///
+/// The protocol doesn't tie replies to commands, so a new message from a bot counts as a reply
+/// to the latest command sent to it, and an error counts against the oldest command that could have caused it.
+/// Update the `State` with every packet first, then pass it to `feed`.
+#[derive(Clone, Debug)]
+pub struct Invocations<C: Clock = SystemClock> {
+    clock: C,
+    active: Vec<Invocation>,
+    last_id: usize
+}
+
+impl Default for Invocations<SystemClock> {
+    fn default() -> Self {
+        Invocations::with_clock(SystemClock)
+    }
+}
+impl Invocations<SystemClock> {
+    /// Create a tracker without any invocations, using the system clock
+    pub fn new() -> Self {
+        Invocations::default()
+    }
+}
+impl<C: Clock> Invocations<C> {
+    /// Create a tracker without any invocations, using a custom clock
+    pub fn with_clock(clock: C) -> Self {
+        Invocations {
+            clock,
+            active: Vec::new(),
+            last_id: 0
+        }
+    }
+    /// Start tracking a command to `bot`, returning its id and the packet to send.
+    /// `Session::invoke_command` does this and sends the packet.
+    pub fn invoke(&mut self, bot: usize, args: Vec<String>) -> (usize, Packet) {
+        self.last_id += 1;
+        let packet = Packet::Command(common::Command {
+            args: args.clone(),
+            recipient: bot
+        });
+        self.active.push(Invocation {
+            id: self.last_id,
+            bot,
+            args,
+            sent: self.clock.now(),
+            replied: false
+        });
+        (self.last_id, packet)
+    }
+    /// Get invocation `id`, if it's still tracked
+    pub fn get(&self, id: usize) -> Option<&Invocation> {
+        self.active.iter().find(|invocation| invocation.id == id)
+    }
+    /// List all tracked invocations, oldest first
+    pub fn active(&self) -> &[Invocation] {
+        &self.active
+    }
+    /// Stop tracking invocation `id`
+    pub fn finish(&mut self, id: usize) -> Option<Invocation> {
+        let index = self.active.iter().position(|invocation| invocation.id == id)?;
+        Some(self.active.remove(index))
+    }
+    /// Stop tracking invocations sent longer than `max_age` ago, returning them
+    pub fn expire(&mut self, max_age: Duration) -> Vec<Invocation> {
+        let now = self.clock.now();
+        let (expired, active) = self.active.drain(..).partition(|invocation| now - invocation.sent > max_age);
+        self.active = active;
+        expired
+    }
+
+    /// Handle a packet
+    pub fn feed(&mut self, state: &State, packet: &Packet) -> InvocationFeed {
+        match *packet {
+            Packet::MessageReceive(ref event) if event.new && state.id != Some(event.inner.author) => {
+                let invocation = self.active.iter_mut()
+                    .rev()
+                    .find(|invocation| invocation.bot == event.inner.author);
+                match invocation {
+                    Some(invocation) => {
+                        invocation.replied = true;
+                        InvocationFeed::Reply { id: invocation.id, message: event.inner.clone() }
+                    },
+                    None => InvocationFeed::Ignored
+                }
+            },
+            Packet::Err(common::ERR_UNKNOWN_BOT) => {
+                // prefer a recipient that isn't known to be a bot, then one that never replied
+                let is_bot = |bot| state.users.get(&bot).map(|user| user.bot).unwrap_or(false);
+                let index = self.active.iter().position(|invocation| !is_bot(invocation.bot))
+                    .or_else(|| self.active.iter().position(|invocation| !invocation.replied));
+                match index {
+                    Some(index) => InvocationFeed::Failed {
+                        id: self.active.remove(index).id,
+                        error: ServerError::UnknownBot
+                    },
+                    None => InvocationFeed::Ignored
+                }
+            },
+            _ => InvocationFeed::Ignored
+        }
+    }
+}
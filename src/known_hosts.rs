@@ -0,0 +1,193 @@
+use crate::fingerprint::Fingerprint;
+use failure::Error;
+use std::error::Error as StdError;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Returned when a server's key hash isn't one of those the known hosts file has for it.
+/// Either the server got a new key, or somebody is pretending to be it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostKeyChanged {
+    /// The host, as written in the known hosts file
+    pub host: String,
+    /// The hashes the known hosts file has for the host
+    pub expected: Vec<String>,
+    /// The hash of the key the server actually used
+    pub actual: String
+}
+
+impl fmt::Display for HostKeyChanged {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "host key for {} changed: expected {}, got {}", self.host, self.expected.join(" or "), self.actual)
+    }
+}
+impl StdError for HostKeyChanged {}
+
+/// What a known hosts file says about a host's key hash
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HostStatus {
+    /// The hash is one of the host's
+    Known,
+    /// There are no hashes for the host
+    Unknown,
+    /// The host has hashes, but not this one
+    Changed
+}
+
+/// Compare two hashes in any format `Fingerprint` can parse, or as plain text otherwise
+fn same_hash(a: &str, b: &str) -> bool {
+    match (a.parse::<Fingerprint>(), b.parse::<Fingerprint>()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a.trim().eq_ignore_ascii_case(b.trim())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Line {
+    Entry { host: String, hash: String },
+    // Comments and blank lines, kept as-is when saving
+    Other(String)
+}
+
+/// A known hosts file, like SSH's, with one `host hash` pair per line.
+/// A host can have multiple lines to accept multiple keys, such as while rotating them.
+/// Lines starting with `#` are comments.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KnownHosts {
+    path: Option<PathBuf>,
+    lines: Vec<Line>
+}
+
+impl KnownHosts {
+    /// Create an empty known hosts store that isn't backed by a file
+    pub fn new() -> Self {
+        KnownHosts::default()
+    }
+    /// Load a known hosts file. A file that doesn't exist yet is treated as empty.
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Self, Error> {
+        let path = path.into();
+        let mut hosts = match fs::read_to_string(&path) {
+            Ok(contents) => KnownHosts::parse(&contents),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => KnownHosts::new(),
+            Err(err) => return Err(err.into())
+        };
+        hosts.path = Some(path);
+        Ok(hosts)
+    }
+    /// Parse the contents of a known hosts file
+    pub fn parse(contents: &str) -> Self {
+        let lines = contents.lines().map(|line| {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                return Line::Other(line.to_string());
+            }
+            let mut parts = trimmed.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some(host), Some(hash)) => Line::Entry {
+                    host: host.to_string(),
+                    hash: hash.to_string()
+                },
+                _ => Line::Other(line.to_string())
+            }
+        }).collect();
+
+        KnownHosts {
+            path: None,
+            lines
+        }
+    }
+    /// Returns the file this was loaded from, if any
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Get all hashes for `host`, in the order they appear in the file
+    pub fn hashes(&self, host: &str) -> Vec<&str> {
+        self.lines.iter()
+            .filter_map(|line| match *line {
+                Line::Entry { host: ref entry, ref hash } if entry == host => Some(&**hash),
+                _ => None
+            })
+            .collect()
+    }
+    /// Check `hash` against the hashes for `host`, ignoring case and format
+    pub fn check(&self, host: &str, hash: &str) -> HostStatus {
+        let hashes = self.hashes(host);
+        if hashes.is_empty() {
+            HostStatus::Unknown
+        } else if hashes.iter().any(|known| same_hash(known, hash)) {
+            HostStatus::Known
+        } else {
+            HostStatus::Changed
+        }
+    }
+    /// Same as `check`, but returns a `HostKeyChanged` error for a changed key,
+    /// and accepts and remembers the hash of an unknown host.
+    /// Returns true if the host was unknown and needs to be saved.
+    pub fn trust(&mut self, host: &str, hash: &str) -> Result<bool, HostKeyChanged> {
+        match self.check(host, hash) {
+            HostStatus::Known => Ok(false),
+            HostStatus::Unknown => {
+                self.add(host, hash);
+                Ok(true)
+            },
+            HostStatus::Changed => Err(HostKeyChanged {
+                host: host.to_string(),
+                expected: self.hashes(host).into_iter().map(String::from).collect(),
+                actual: hash.trim().to_string()
+            })
+        }
+    }
+
+    /// Accept `hash` for `host`, in addition to any it already has
+    pub fn add<S: Into<String>>(&mut self, host: S, hash: &str) {
+        let host = host.into();
+        if self.hashes(&host).iter().any(|known| same_hash(known, hash)) {
+            return;
+        }
+        self.lines.push(Line::Entry {
+            host,
+            hash: hash.trim().to_string()
+        });
+    }
+    /// Stop accepting `hash` for `host`
+    pub fn remove(&mut self, host: &str, hash: &str) {
+        self.lines.retain(|line| match *line {
+            Line::Entry { host: ref entry, hash: ref known } => entry != host || !same_hash(known, hash),
+            Line::Other(_) => true
+        });
+    }
+    /// Forget all hashes for `host`
+    pub fn remove_host(&mut self, host: &str) {
+        self.lines.retain(|line| match *line {
+            Line::Entry { host: ref entry, .. } => entry != host,
+            Line::Other(_) => true
+        });
+    }
+
+    /// Write the file this was loaded from
+    pub fn save(&self) -> Result<(), Error> {
+        match self.path {
+            Some(ref path) => self.save_to(path),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "known hosts store has no file").into())
+        }
+    }
+    /// Write to `path`
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        Ok(fs::write(path, self.to_string())?)
+    }
+}
+
+impl fmt::Display for KnownHosts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            match *line {
+                Line::Entry { ref host, ref hash } => writeln!(f, "{} {}", host, hash)?,
+                Line::Other(ref line) => writeln!(f, "{}", line)?
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,247 +1,164 @@
-#[cfg(feature = "tokio")] extern crate futures;
-#[cfg(feature = "tokio")] extern crate tokio_core;
-#[cfg(feature = "tokio")] extern crate tokio_io;
+#[cfg(feature = "rustls")] extern crate base64;
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] extern crate bytes;
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] extern crate futures;
+#[cfg(feature = "rustls")] extern crate ring;
+#[cfg(feature = "rustls")] extern crate rustls;
+#[cfg(feature = "rustls")] extern crate rustls_native_certs;
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] extern crate tokio;
 #[cfg(feature = "tokio")] extern crate tokio_openssl;
+#[cfg(feature = "tokio-rustls")] extern crate tokio_rustls;
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] extern crate tokio_util;
+#[cfg(feature = "rustls")] extern crate webpki;
 #[macro_use] extern crate failure;
-extern crate openssl;
+#[cfg(feature = "openssl")] extern crate openssl;
 extern crate rmp_serde as rmps;
+extern crate serde;
+extern crate socket2;
 
 // Dependencies of common
 #[macro_use] extern crate serde_derive;
 // End
 
+#[cfg(not(any(feature = "openssl", feature = "rustls")))]
+compile_error!("synac needs a TLS backend, enable either the \"openssl\" or the \"rustls\" feature");
+
+#[macro_use] mod requests;
+
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))]
+/// The async session, built on tokio
+pub mod asynchronous;
+/// The blocking session
+pub mod blocking;
+/// Connects sessions with custom TLS and socket options
+pub mod builder;
+/// Remembers recent messages
+pub mod cache;
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))]
+/// Packet framing for async streams
+pub mod codec;
+/// Routes bot commands to handlers
+pub mod commands;
 /// Files shared by both the client and server
 pub mod common;
-#[cfg(not(feature = "tokio"))]
+/// Typed errors the server can respond with
+pub mod error;
+/// Hashes of server keys, which sessions are pinned to
+pub mod fingerprint;
+/// Callbacks for each kind of packet
+pub mod handler;
+/// Walks through message history
+pub mod history;
+/// Follows up on commands sent to bots
+pub mod invocations;
+/// Remembers the keys of servers
+pub mod known_hosts;
 /// A non-blocking listener
 pub mod listener;
+/// Permission sets for channel modes
+pub mod mode;
+/// Paces outgoing packets around rate limits
+pub mod send_queue;
 /// Remembers stuff previous packets have informed about
 pub mod state;
-
-#[cfg(not(feature = "tokio"))] pub use listener::*;
+#[cfg(feature = "server")]
+/// An in-memory reference server
+pub mod server;
+/// Reconnects automatically when the connection dies
+pub mod supervisor;
+#[cfg(feature = "testing")]
+/// A scripted mock server for testing bots
+pub mod testing;
+/// Keeps track of who is typing
+pub mod typing;
+/// Hashing and encoding using whichever TLS backend is enabled
+mod crypto;
+#[cfg(feature = "openssl")]
+/// TLS setup for openssl
+mod tls_openssl;
+#[cfg(feature = "rustls")]
+/// TLS setup for rustls
+mod tls_rustls;
+
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] pub use asynchronous::Session as AsyncSession;
+pub use blocking::{Session, Stream};
+#[cfg(feature = "openssl")] pub use blocking::OpensslStream;
+#[cfg(feature = "rustls")] pub use blocking::RustlsStream;
+pub use builder::*;
+pub use cache::*;
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] pub use codec::*;
+pub use commands::*;
+pub use error::*;
+pub use fingerprint::{Fingerprint, InvalidFingerprint};
+pub use handler::*;
+pub use history::*;
+pub use invocations::*;
+pub use known_hosts::*;
+pub use listener::*;
+pub use mode::*;
+pub use send_queue::*;
 pub use state::*;
+pub use supervisor::*;
+pub use typing::*;
 
-#[cfg(feature = "tokio")] use futures::{future, Future};
-#[cfg(feature = "tokio")] use std::net::SocketAddr;
-#[cfg(feature = "tokio")] use std::rc::Rc;
-#[cfg(feature = "tokio")] use tokio_core::net::TcpStream;
-#[cfg(feature = "tokio")] use tokio_core::reactor::Handle;
-#[cfg(feature = "tokio")] use tokio_io::{io, AsyncRead};
-#[cfg(feature = "tokio")] use tokio_openssl::{ConnectConfigurationExt, SslStream};
-#[cfg(not(feature = "tokio"))] use openssl::ssl::SslStream;
-#[cfg(not(feature = "tokio"))] use std::net::TcpStream;
-#[cfg(not(feature = "tokio"))] use std::net::ToSocketAddrs;
 use common::Packet;
 use failure::Error;
-use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
-use openssl::x509::X509StoreContextRef;
-use std::any::Any;
-use std::fmt::Write;
-
-pub type Stream = SslStream<TcpStream>;
-
-#[cfg(not(feature = "tokio"))]
-/// A struct that holds the connection to synac.
-pub struct Session {
-    stream: Stream
-}
-
-#[cfg(feature = "tokio")]
-/// A struct that holds the connection to synac.
-pub struct Session {
-    reader: Option<io::ReadHalf<Stream>>,
-    writer: io::WriteHalf<Stream>
+use std::collections::VecDeque;
+
+/// The result of a successful login
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoginOutcome {
+    /// Your user id
+    pub id: usize,
+    /// The token to use next time you log in
+    pub token: String,
+    /// True if the account was just created
+    pub created: bool
 }
 
-impl Session {
-    #[cfg(not(feature = "tokio"))]
-    /// Create a synac session that verifies the public key against a hash.
-    pub fn new<S: Into<String>, T: ToSocketAddrs>(addr: T, hash: S) -> Result<Session, Error> {
-        let hash = hash.into();
-        Self::new_with_verify_callback(addr, move |_, cert| {
-            if let Some(cert) = cert.current_cert() {
-                if let Ok(pkey) = cert.public_key() {
-                    if let Ok(pem) = pkey.public_key_to_pem() {
-                        let digest = openssl::sha::sha256(&pem);
-                        let mut digest_string = String::with_capacity(digest.len());
-                        for byte in &digest {
-                            write!(digest_string, "{:02X}", byte).unwrap();
-                        }
-                        return hash.trim().eq_ignore_ascii_case(&digest_string);
-                    }
-                }
-            }
-            false
-        })
-    }
-    #[cfg(feature = "tokio")]
-    /// Create a synac session that verifies the public key against a hash.
-    pub fn new<S: Into<String>>(addr: &SocketAddr, hash: S, handle: &Handle)
-        -> Box<Future<Item = Session, Error = Error>>
-    {
-        let hash = hash.into();
-        Self::new_with_verify_callback(addr, move |_, cert| {
-            if let Some(cert) = cert.current_cert() {
-                if let Ok(pkey) = cert.public_key() {
-                    if let Ok(pem) = pkey.public_key_to_pem() {
-                        let digest = openssl::sha::sha256(&pem);
-                        let mut digest_string = String::with_capacity(digest.len());
-                        for byte in &digest {
-                            write!(digest_string, "{:02X}", byte).unwrap();
-                        }
-                        return hash.trim().eq_ignore_ascii_case(&digest_string);
-                    }
-                }
-            }
-            false
-        }, handle)
-    }
-
-    #[cfg(not(feature = "tokio"))]
-    /// Create a synac session with a custom SSL callback.
-    pub fn new_with_verify_callback<T, F>(addr: T, callback: F)
-        -> Result<Session, Error>
-        where
-            T: ToSocketAddrs,
-            F: Fn(bool, &mut X509StoreContextRef) -> bool + Any + 'static + Sync + Send
-    {
-        let mut config = SslConnector::builder(SslMethod::tls())?;
-        config.set_verify_callback(SslVerifyMode::PEER, callback);
-        let connector = config.build();
-
-        let stream = TcpStream::connect(addr)?;
-        let stream = connector.configure()?
-                        .use_server_name_indication(false)
-                        .verify_hostname(false)
-                        .connect("", stream)?;
-
-        Ok(Session {
-            stream: stream
-        })
-    }
-    #[cfg(feature = "tokio")]
-    /// Create a synac session with a custom SSL callback.
-    pub fn new_with_verify_callback<F>(addr: &SocketAddr, callback: F, handle: &Handle)
-        -> Box<Future<Item = Session, Error = Error>>
-        where
-            F: Fn(bool, &mut X509StoreContextRef) -> bool + Any + 'static + Sync + Send
-    {
-        let mut config = match SslConnector::builder(SslMethod::tls()) {
-            Ok(config) => config,
-            Err(err) => return Box::new(future::err(Error::from(err)))
-        };
-        config.set_verify_callback(SslVerifyMode::PEER, callback);
-        let connector = config.build();
-
-        Box::new(TcpStream::connect(addr, handle)
-            .map_err(Error::from)
-            .and_then(move |stream| -> Box<Future<Item = Session, Error = Error>> {
-                let config = match connector.configure() {
-                    Ok(config) => config,
-                    Err(err) => return Box::new(future::err(Error::from(err)))
-                };
-                Box::new(config
-                    .use_server_name_indication(false)
-                    .verify_hostname(false)
-                    .connect_async("", stream)
-                    .map_err(Error::from)
-                    .map(|stream| {
-                        let (reader, writer) = stream.split();
-                        Session {
-                            reader: Some(reader),
-                            writer: writer
-                        }
-                    }))
-        }))
-    }
-
-    #[cfg(not(feature = "tokio"))]
-    /// Returns inner connection
-    pub fn inner_stream(&mut self) -> &mut Stream {
-        &mut self.stream
-    }
-
-    #[cfg(not(feature = "tokio"))]
-    /// Makes inner stream non-blocking
-    pub fn set_nonblocking(&mut self, value: bool) -> Result<(), std::io::Error> {
-        self.stream.get_ref().set_nonblocking(value)
-    }
-
-    /// Sends the login packet with specific password.
-    /// Read the result with `read`.
-    /// Warning: Strongly disencouraged. Use tokens instead, when possible.
-    pub fn login_with_password<S: Into<String>>(&mut self, bot: bool, name: S, password: S) -> Result<(), Error> {
-        self.write(&Packet::Login(common::Login {
-            bot: bot,
-            name: name.into(),
-            password: Some(password.into()),
-            token: None
-        }))
-    }
-    /// Sends the login packet with specific token.
-    /// Read the result with `read`.
-    pub fn login_with_token<S: Into<String>>(&mut self, bot: bool, name: S, token: S) -> Result<(), Error> {
-        self.write(&Packet::Login(common::Login {
-            bot: bot,
-            name: name.into(),
-            password: None,
-            token: Some(token.into())
-        }))
-    }
-
-    #[cfg(not(feature = "tokio"))]
-    /// Transmit a packet over the connection
-    pub fn write(&mut self, packet: &Packet) -> Result<(), Error> {
-        Ok(common::write(&mut self.stream, packet)?)
-    }
-    #[cfg(feature = "tokio")]
-    /// Transmit a packet over the connection
-    pub fn write(&mut self, packet: &Packet) -> Result<(), Error> {
-        Ok(common::write(&mut self.writer, packet)?)
-    }
-
-    #[cfg(not(feature = "tokio"))]
-    /// Read a packet from the connection
-    pub fn read(&mut self) -> Result<Packet, Error> {
-        Ok(common::read(&mut self.stream)?)
-    }
-    #[cfg(feature = "tokio")]
-    /// Read a packet from the connection
-    pub fn read_loop<F: Fn(Packet) + 'static>(&mut self, callback: F)
-        -> Box<Future<Item = (), Error = Error>> // TODO: impl Future
-    {
-        let reader = self.reader.take().expect("A read loop already exists");
-        let callback = Rc::new(callback);
-
-        Box::new(future::loop_fn(reader, move |reader| {
-            let callback = Rc::clone(&callback);
-
-            io::read_exact(reader, [0; 2])
-                .map_err(Error::from)
-                .and_then(move |(reader, buf)| {
-                    let callback = Rc::clone(&callback);
-                    let size = common::decode_u16(&buf);
-
-                    io::read_exact(reader, vec![0; size as usize])
-                        .map_err(Error::from)
-                        .and_then(move |(reader, buf)| {
-                            let packet = match common::deserialize(&buf) {
-                                Ok(packet) => packet,
-                                Err(err) => return Err(err.into())
-                            };
-                            callback(packet);
-                            Ok(future::Loop::Continue(reader))
-                        })
-                })
-        }))
+// Shared by both sessions while waiting for the login result.
+// Returns `None` and buffers the packet if it isn't one.
+pub(crate) fn handle_login(buffer: &mut VecDeque<Packet>, packet: Packet) -> Option<Result<LoginOutcome, Error>> {
+    match packet {
+        Packet::LoginSuccess(event) => {
+            let outcome = LoginOutcome {
+                id: event.id,
+                token: event.token.clone(),
+                created: event.created
+            };
+            buffer.push_back(Packet::LoginSuccess(event));
+            Some(Ok(outcome))
+        },
+        Packet::Err(code) => Some(Err(ServerError::from(code).into())),
+        Packet::RateLimited(seconds) => Some(Err(RateLimited(seconds).into())),
+        packet => {
+            buffer.push_back(packet);
+            None
+        }
     }
 }
 
-/// Get the mode bitmask for a user in a channel
-pub fn get_mode(channel: &common::Channel, user: &common::User) -> u8 {
+/// Get the mode for a user in a channel
+pub fn get_mode(channel: &common::Channel, user: &common::User) -> Mode {
     if user.bot {
-        user.modes.get(&channel.id).cloned().unwrap_or(channel.default_mode_bot)
+        Mode::from(user.modes.get(&channel.id).cloned().unwrap_or(channel.default_mode_bot))
+    } else {
+        Mode::from(user.modes.get(&channel.id).cloned().unwrap_or(channel.default_mode_user))
+    }
+}
+/// Get the mode a user actually has in a channel, following the same rules as the server:
+///
+/// - Banned users can't do anything.
+/// - In private channels, only the user's own mode counts. Admins are not exempt.
+/// - Admins can do everything in all other channels.
+/// - Anybody else gets what `get_mode` returns.
+pub fn get_effective_mode(channel: &common::Channel, user: &common::User) -> Mode {
+    if user.ban {
+        Mode::empty()
+    } else if channel.private {
+        user.modes.get(&channel.id).cloned().map(Mode::from).unwrap_or_default()
+    } else if user.admin {
+        Mode::ALL
     } else {
-        user.modes.get(&channel.id).cloned().unwrap_or(channel.default_mode_user)
+        get_mode(channel, user)
     }
 }
@@ -1,6 +1,6 @@
-use common::{self, Packet};
+use crate::common::{self, Packet};
 use failure::Error;
-use std::io::{ErrorKind as IoErrorKind, Read};
+use std::io::{self, ErrorKind as IoErrorKind, Read};
 
 /// A non-blocking listener
 pub struct Listener {
@@ -35,22 +35,39 @@ impl Listener {
             return Ok(None);
         }
         self.i += read;
-
-        if self.i >= self.buf.len() {
-            if self.size {
-                self.size = false;
-                let size = common::decode_u16(&self.buf) as usize;
-                self.buf = vec![0; size];
-                self.i = 0;
-            } else {
-                let packet = common::deserialize(&self.buf)?;
-
-                self.size = true;
-                self.buf = vec![0; 2];
-                self.i = 0;
-                return Ok(Some(packet));
+        Ok(self.complete()?)
+    }
+    /// Read until a whole packet arrived. If reading fails, such as with a timeout,
+    /// what was read so far is kept for the next call, so the framing stays intact.
+    pub(crate) fn read<S: Read>(&mut self, stream: &mut S) -> Result<Packet, common::Error> {
+        loop {
+            if let Some(packet) = self.complete()? {
+                return Ok(packet);
             }
+            match stream.read(&mut self.buf[self.i..]) {
+                Ok(0) => return Err(io::Error::from(IoErrorKind::UnexpectedEof).into()),
+                Ok(read) => self.i += read,
+                Err(ref err) if err.kind() == IoErrorKind::Interrupted => (),
+                Err(err) => return Err(err.into())
+            }
+        }
+    }
+    /// Move on once the size or packet buffer is full
+    fn complete(&mut self) -> Result<Option<Packet>, common::Error> {
+        if self.i < self.buf.len() {
+            return Ok(None);
+        }
+        if self.size {
+            self.size = false;
+            let size = common::decode_u16(&self.buf) as usize;
+            self.buf = vec![0; size];
+            self.i = 0;
+            return Ok(None);
         }
-        Ok(None)
+        // start over even if the packet is invalid, the next one may not be
+        let buf = std::mem::replace(&mut self.buf, vec![0; 2]);
+        self.size = true;
+        self.i = 0;
+        Ok(Some(common::deserialize(&buf)?))
     }
 }
@@ -0,0 +1,232 @@
+use crate::common;
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::error::Error as StdError;
+use std::fmt;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not, Sub, SubAssign};
+use std::str::FromStr;
+
+/// Names, display letters and bits of all known permissions, in display order
+const PERMS: [(&str, char, u8); 5] = [
+    ("read",            'r', common::PERM_READ),
+    ("write",           'w', common::PERM_WRITE),
+    ("manage_channels", 'c', common::PERM_MANAGE_CHANNELS),
+    ("manage_messages", 'd', common::PERM_MANAGE_MESSAGES),
+    ("manage_modes",    'm', common::PERM_MANAGE_MODES)
+];
+
+/// A set of channel permissions.
+/// Serializes as the same `u8` bitmask used by the `PERM_*` constants,
+/// so it's wire compatible with the mode fields in `common`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Mode(u8);
+
+impl Mode {
+    pub const READ:            Mode = Mode(common::PERM_READ);
+    pub const WRITE:           Mode = Mode(common::PERM_WRITE);
+    pub const MANAGE_CHANNELS: Mode = Mode(common::PERM_MANAGE_CHANNELS);
+    pub const MANAGE_MESSAGES: Mode = Mode(common::PERM_MANAGE_MESSAGES);
+    pub const MANAGE_MODES:    Mode = Mode(common::PERM_MANAGE_MODES);
+    pub const ALL:             Mode = Mode(common::PERM_ALL);
+
+    /// A mode with no permissions
+    pub fn empty() -> Self {
+        Mode(0)
+    }
+    /// A mode with all known permissions
+    pub fn all() -> Self {
+        Mode::ALL
+    }
+    /// Creates a mode from raw bits, keeping unknown bits as-is
+    pub fn from_bits(bits: u8) -> Self {
+        Mode(bits)
+    }
+    /// Creates a mode from raw bits, dropping any unknown bits
+    pub fn from_bits_truncate(bits: u8) -> Self {
+        Mode(bits & common::PERM_ALL)
+    }
+    /// Returns the raw bitmask
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+    /// Returns true if no permissions are set
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+    /// Returns true if all permissions in `other` are set
+    pub fn contains(&self, other: Mode) -> bool {
+        self.0 & other.0 == other.0
+    }
+    /// Returns true if any permission in `other` is set
+    pub fn intersects(&self, other: Mode) -> bool {
+        self.0 & other.0 != 0
+    }
+    /// Sets all permissions in `other`
+    pub fn insert(&mut self, other: Mode) {
+        self.0 |= other.0;
+    }
+    /// Unsets all permissions in `other`
+    pub fn remove(&mut self, other: Mode) {
+        self.0 &= !other.0;
+    }
+    /// Sets or unsets all permissions in `other`
+    pub fn set(&mut self, other: Mode, value: bool) {
+        if value {
+            self.insert(other);
+        } else {
+            self.remove(other);
+        }
+    }
+
+    /// Applies a list of whitespace or comma separated changes to this mode,
+    /// such as `"+write -manage_messages"`.
+    /// A name without a prefix is the same as prefixing it with `+`.
+    /// `all` and `none` are accepted as names, and so is the `Display` form (like `rw--m`),
+    /// which replaces the whole mode instead.
+    pub fn apply(&self, changes: &str) -> Result<Mode, ParseModeError> {
+        let mut mode = *self;
+
+        for change in changes.split(|c: char| c.is_whitespace() || c == ',').filter(|s| !s.is_empty()) {
+            if let Some(parsed) = parse_letters(change) {
+                mode = parsed;
+                continue;
+            }
+
+            let (add, name) = if let Some(name) = change.strip_prefix('+') {
+                (true, name)
+            } else if let Some(name) = change.strip_prefix('-') {
+                (false, name)
+            } else {
+                (true, change)
+            };
+            let perm = parse_name(name).ok_or_else(|| ParseModeError(change.to_string()))?;
+            if perm.is_empty() {
+                // `none` clears everything, `-none` does nothing
+                if add {
+                    mode = Mode::empty();
+                }
+            } else {
+                mode.set(perm, add);
+            }
+        }
+
+        Ok(mode)
+    }
+}
+
+fn parse_name(name: &str) -> Option<Mode> {
+    match &*name.to_lowercase() {
+        "all" => Some(Mode::ALL),
+        "none" => Some(Mode::empty()),
+        name => PERMS.iter()
+            .find(|&&(perm, _, _)| perm == name)
+            .map(|&(_, _, bits)| Mode(bits))
+    }
+}
+fn parse_letters(input: &str) -> Option<Mode> {
+    if input.chars().count() != PERMS.len() {
+        return None;
+    }
+    let mut mode = Mode::empty();
+    for (c, &(_, letter, bits)) in input.chars().zip(PERMS.iter()) {
+        if c == letter {
+            mode.insert(Mode(bits));
+        } else if c != '-' {
+            return None;
+        }
+    }
+    Some(mode)
+}
+
+impl From<u8> for Mode {
+    fn from(bits: u8) -> Self {
+        Mode(bits)
+    }
+}
+impl From<Mode> for u8 {
+    fn from(mode: Mode) -> Self {
+        mode.0
+    }
+}
+
+impl BitOr for Mode {
+    type Output = Mode;
+    fn bitor(self, other: Mode) -> Mode {
+        Mode(self.0 | other.0)
+    }
+}
+impl BitOrAssign for Mode {
+    fn bitor_assign(&mut self, other: Mode) {
+        self.0 |= other.0;
+    }
+}
+impl BitAnd for Mode {
+    type Output = Mode;
+    fn bitand(self, other: Mode) -> Mode {
+        Mode(self.0 & other.0)
+    }
+}
+impl BitAndAssign for Mode {
+    fn bitand_assign(&mut self, other: Mode) {
+        self.0 &= other.0;
+    }
+}
+impl Sub for Mode {
+    type Output = Mode;
+    fn sub(self, other: Mode) -> Mode {
+        Mode(self.0 & !other.0)
+    }
+}
+impl SubAssign for Mode {
+    fn sub_assign(&mut self, other: Mode) {
+        self.0 &= !other.0;
+    }
+}
+impl Not for Mode {
+    type Output = Mode;
+    fn not(self) -> Mode {
+        Mode(!self.0 & common::PERM_ALL)
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &(_, letter, bits) in &PERMS {
+            if self.contains(Mode(bits)) {
+                write!(f, "{}", letter)?;
+            } else {
+                write!(f, "-")?;
+            }
+        }
+        Ok(())
+    }
+}
+impl FromStr for Mode {
+    type Err = ParseModeError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Mode::empty().apply(input)
+    }
+}
+
+impl Serialize for Mode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.0)
+    }
+}
+impl<'de> Deserialize<'de> for Mode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u8::deserialize(deserializer).map(Mode)
+    }
+}
+
+/// Returned when parsing a mode fails
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseModeError(pub String);
+
+impl fmt::Display for ParseModeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown permission: {}", self.0)
+    }
+}
+impl StdError for ParseModeError {}
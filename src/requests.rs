@@ -0,0 +1,182 @@
+// The typed request methods are the same for every session,
+// except that async sessions need to await the write.
+// `request_methods!(blocking)` or `request_methods!(async)` generates them inside an impl block.
+// They only fail if sending fails, the server's answer (including `Packet::Err`) arrives later.
+
+macro_rules! request_methods {
+    (@expand blocking $(
+        $(#[$meta:meta])*
+        fn $name:ident($($arg:ident: $ty:ty),*) $packet:block
+    )*) => {
+        $(
+            $(#[$meta])*
+            pub fn $name(&mut self, $($arg: $ty),*) -> Result<(), Error> {
+                self.write(&$packet)
+            }
+        )*
+    };
+    (@expand async $(
+        $(#[$meta:meta])*
+        fn $name:ident($($arg:ident: $ty:ty),*) $packet:block
+    )*) => {
+        $(
+            $(#[$meta])*
+            pub async fn $name(&mut self, $($arg: $ty),*) -> Result<(), Error> {
+                self.write(&$packet).await
+            }
+        )*
+    };
+    ($mode:tt) => {
+        request_methods! { @expand $mode
+            /// Sends the login packet with specific password.
+            /// Read the result with `read`.
+            /// Warning: Strongly disencouraged. Use tokens instead, when possible.
+            fn login_with_password(bot: bool, name: impl Into<String>, password: impl Into<String>) {
+                Packet::Login(common::Login {
+                    bot,
+                    name: name.into(),
+                    password: Some(password.into()),
+                    token: None
+                })
+            }
+            /// Sends the login packet with specific token.
+            /// Read the result with `read`.
+            fn login_with_token(bot: bool, name: impl Into<String>, token: impl Into<String>) {
+                Packet::Login(common::Login {
+                    bot,
+                    name: name.into(),
+                    password: None,
+                    token: Some(token.into())
+                })
+            }
+            /// Sends a login update, for changing name, password and/or token.
+            /// `reset_token` is ignored and treated as true if a new password is set.
+            fn update_login(name: Option<String>, password_current: Option<String>,
+                            password_new: Option<String>, reset_token: bool) {
+                Packet::LoginUpdate(common::LoginUpdate {
+                    name,
+                    password_current,
+                    password_new,
+                    reset_token
+                })
+            }
+
+            /// Sends a message to `channel`
+            fn send_message(channel: usize, text: impl Into<Vec<u8>>) {
+                Packet::MessageCreate(common::MessageCreate {
+                    channel,
+                    text: text.into()
+                })
+            }
+            /// Replaces the text of message `id`
+            fn edit_message(id: usize, text: impl Into<Vec<u8>>) {
+                Packet::MessageUpdate(common::MessageUpdate {
+                    id,
+                    text: text.into()
+                })
+            }
+            /// Deletes message `id`
+            fn delete_message(id: usize) {
+                Packet::MessageDelete(common::MessageDelete {
+                    id
+                })
+            }
+            /// Deletes all messages in `ids` from `channel`.
+            /// The server accepts at most `common::LIMIT_BULK` ids at a time.
+            fn delete_messages(channel: usize, ids: Vec<usize>) {
+                Packet::MessageDeleteBulk(common::MessageDeleteBulk {
+                    channel,
+                    ids
+                })
+            }
+            /// Requests the `limit` most recent messages in `channel`, optionally before/after a message id.
+            /// The messages are sent as `MessageReceive` packets, followed by `MessageListReceived`.
+            fn list_messages(channel: usize, limit: usize, before: Option<usize>, after: Option<usize>) {
+                Packet::MessageList(common::MessageList {
+                    after,
+                    before,
+                    channel,
+                    limit
+                })
+            }
+            /// Sends a typing indicator to `channel`. It times out after `common::TYPING_TIMEOUT` seconds.
+            fn send_typing(channel: usize) {
+                Packet::Typing(common::Typing {
+                    channel
+                })
+            }
+
+            /// Creates a new public channel with the specified default modes
+            fn create_channel(name: impl Into<String>, default_mode_bot: Mode, default_mode_user: Mode) {
+                Packet::ChannelCreate(common::ChannelCreate {
+                    default_mode_bot: default_mode_bot.bits(),
+                    default_mode_user: default_mode_user.bits(),
+                    name: name.into(),
+                    recipient: None
+                })
+            }
+            /// Creates a new private channel with `recipient`
+            fn create_private_channel(recipient: usize) {
+                Packet::ChannelCreate(common::ChannelCreate {
+                    recipient: Some(recipient),
+                    ..Default::default()
+                })
+            }
+            /// Replaces a channel's name and default modes with the ones in `channel`
+            fn update_channel(channel: common::Channel) {
+                Packet::ChannelUpdate(common::ChannelUpdate {
+                    inner: channel
+                })
+            }
+            /// Deletes channel `id`
+            fn delete_channel(id: usize) {
+                Packet::ChannelDelete(common::ChannelDelete {
+                    id
+                })
+            }
+
+            /// Updates user `id`. Fields set to `None` are left unchanged.
+            /// `channel_mode` is a channel id and the new mode, where a mode of `None` resets it to the channel default.
+            fn update_user(id: usize, admin: Option<bool>, ban: Option<bool>, channel_mode: Option<(usize, Option<Mode>)>) {
+                Packet::UserUpdate(common::UserUpdate {
+                    admin,
+                    ban,
+                    channel_mode: channel_mode.map(|(channel, mode)| (channel, mode.map(u8::from))),
+                    id
+                })
+            }
+            /// Grants or revokes admin for user `id`
+            fn set_admin(id: usize, admin: bool) {
+                Packet::UserUpdate(common::UserUpdate {
+                    admin: Some(admin),
+                    id,
+                    ..Default::default()
+                })
+            }
+            /// Bans or unbans user `id`
+            fn set_ban(id: usize, ban: bool) {
+                Packet::UserUpdate(common::UserUpdate {
+                    ban: Some(ban),
+                    id,
+                    ..Default::default()
+                })
+            }
+            /// Sets the mode of user `id` in `channel`. `None` resets it to the channel default.
+            fn set_mode(id: usize, channel: usize, mode: Option<Mode>) {
+                Packet::UserUpdate(common::UserUpdate {
+                    channel_mode: Some((channel, mode.map(u8::from))),
+                    id,
+                    ..Default::default()
+                })
+            }
+
+            /// Sends a command to the bot `recipient`
+            fn send_command(recipient: usize, args: Vec<String>) {
+                Packet::Command(common::Command {
+                    args,
+                    recipient
+                })
+            }
+        }
+    };
+}
@@ -0,0 +1,324 @@
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] use crate::asynchronous;
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))] use tokio::io::{AsyncRead, AsyncWrite};
+use crate::blocking::Session;
+use crate::common::Packet;
+use crate::typing::{Clock, SystemClock};
+use failure::Error;
+use std::cmp;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+/// How many sent packets are remembered in case the server drops them
+const SENT_HISTORY: usize = 64;
+
+/// Counters describing what a `SendQueue` has done so far
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SendStats {
+    /// Packets waiting to be sent right now
+    pub queued: usize,
+    /// The most packets that have been waiting at once
+    pub max_queued: usize,
+    /// Packets sent, including retries
+    pub sent: u64,
+    /// Packets the server dropped and that were queued again
+    pub retried: u64,
+    /// Packets the server dropped that were too old to be retried
+    pub lost: u64,
+    /// `RateLimited` packets received
+    pub rate_limited: u64,
+    /// The longest a packet has waited in the queue
+    pub max_wait: Duration,
+    /// How long all sent packets have waited in total, divide by `sent` for the average
+    pub total_wait: Duration
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TokenBucket {
+    capacity: u32,
+    tokens: u32,
+    interval: Duration,
+    last: Instant
+}
+
+impl TokenBucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last);
+        let earned = u32::try_from(elapsed.as_nanos() / cmp::max(self.interval.as_nanos(), 1)).unwrap_or(u32::MAX);
+        if self.tokens.saturating_add(earned) >= self.capacity {
+            self.tokens = self.capacity;
+            self.last = now;
+        } else {
+            self.tokens += earned;
+            self.last += self.interval * earned;
+        }
+    }
+    fn wait(&self, now: Instant) -> Duration {
+        if self.tokens > 0 {
+            Duration::from_secs(0)
+        } else {
+            (self.last + self.interval).saturating_duration_since(now)
+        }
+    }
+}
+
+/// Queues outgoing packets, pausing when the server says you're rate limited
+/// and sending the packets it dropped again afterwards,
+/// without caring how packets are sent and received.
+///
+/// `push` packets instead of writing them, pass every received packet to `update`,
+/// and send what `pop` returns. `QueuedSession` does all of that for a session.
+/// While packets are waiting, `delay` tells how long until the next one can be sent.
+///
+/// The server doesn't say which packets it dropped, only how many, and those are always the latest ones sent.
+/// So every packet sent while the queue is in use has to either come from `pop` or be passed to `record`.
+/// Otherwise packets the server accepted could be sent again.
+pub struct SendQueue<C: Clock = SystemClock> {
+    clock: C,
+    queue: VecDeque<(Instant, Packet)>,
+    sent: VecDeque<(Instant, Packet)>,
+    paused_until: Option<Instant>,
+    dropped: usize,
+    bucket: Option<TokenBucket>,
+    stats: SendStats
+}
+
+impl Default for SendQueue<SystemClock> {
+    fn default() -> Self {
+        SendQueue::with_clock(SystemClock)
+    }
+}
+impl SendQueue<SystemClock> {
+    /// Create an empty queue using the system clock
+    pub fn new() -> Self {
+        SendQueue::default()
+    }
+}
+impl<C: Clock> SendQueue<C> {
+    /// Create an empty queue using a custom clock
+    pub fn with_clock(clock: C) -> Self {
+        SendQueue {
+            clock,
+            queue: VecDeque::new(),
+            sent: VecDeque::new(),
+            paused_until: None,
+            dropped: 0,
+            bucket: None,
+            stats: SendStats::default()
+        }
+    }
+    /// Limit sending on the client side to avoid being rate limited at all:
+    /// at most `burst` packets at once, then one per `interval`.
+    /// A `burst` of 0 is treated as 1, since nothing could be sent otherwise.
+    pub fn token_bucket(mut self, burst: u32, interval: Duration) -> Self {
+        let burst = cmp::max(burst, 1);
+        self.bucket = Some(TokenBucket {
+            capacity: burst,
+            tokens: burst,
+            interval,
+            last: self.clock.now()
+        });
+        self
+    }
+
+    /// Queue `packet` to be sent
+    pub fn push(&mut self, packet: Packet) {
+        self.queue.push_back((self.clock.now(), packet));
+        self.stats.max_queued = cmp::max(self.stats.max_queued, self.queue.len());
+    }
+    /// Remember a packet that was sent without going through the queue,
+    /// so it's sent again if the server dropped it
+    pub fn record(&mut self, packet: Packet) {
+        self.remember(self.clock.now(), packet);
+    }
+    /// Handle a received packet. Returns true if it was `RateLimited`.
+    pub fn update(&mut self, packet: &Packet) -> bool {
+        let seconds = match *packet {
+            Packet::RateLimited(seconds) => seconds,
+            _ => return false
+        };
+        self.stats.rate_limited += 1;
+        // every packet sent while limited is dropped and answered with its own `RateLimited`,
+        // so only the first one of a pause says when it ends
+        if self.paused_until.is_none() {
+            self.paused_until = Some(self.clock.now() + Duration::from_secs(seconds));
+        }
+        self.dropped += 1;
+        true
+    }
+    /// Get the next packet to send now, if any
+    pub fn pop(&mut self) -> Option<Packet> {
+        let now = self.clock.now();
+        self.resume(now);
+        if self.wait(now)? > Duration::from_secs(0) {
+            return None;
+        }
+        if let Some(ref mut bucket) = self.bucket {
+            bucket.tokens -= 1;
+        }
+
+        let (queued, packet) = self.queue.pop_front()?;
+        let wait = now.saturating_duration_since(queued);
+        self.stats.sent += 1;
+        self.stats.max_wait = cmp::max(self.stats.max_wait, wait);
+        self.stats.total_wait += wait;
+        self.remember(queued, packet.clone());
+        Some(packet)
+    }
+    /// How long until `pop` returns the next packet, or `None` if nothing is queued
+    pub fn delay(&mut self) -> Option<Duration> {
+        let now = self.clock.now();
+        self.resume(now);
+        self.wait(now)
+    }
+
+    /// Returns true if the server said you're rate limited and the pause isn't over
+    pub fn is_paused(&self) -> bool {
+        self.paused_until.map(|until| until > self.clock.now()).unwrap_or(false)
+    }
+    /// Returns the number of packets waiting to be sent
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+    /// Returns true if no packets are waiting to be sent
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+    /// Get the stats so far
+    pub fn stats(&self) -> SendStats {
+        SendStats {
+            queued: self.queue.len(),
+            ..self.stats
+        }
+    }
+
+    fn remember(&mut self, queued: Instant, packet: Packet) {
+        if self.sent.len() == SENT_HISTORY {
+            self.sent.pop_front();
+        }
+        self.sent.push_back((queued, packet));
+    }
+    fn resume(&mut self, now: Instant) {
+        match self.paused_until {
+            Some(until) if until <= now => (),
+            _ => return
+        }
+        self.paused_until = None;
+        // start slowly, the server just said it was too fast
+        if let Some(ref mut bucket) = self.bucket {
+            bucket.tokens = 0;
+            bucket.last = now;
+        }
+
+        // the dropped packets are the last ones sent, put them back in the same order
+        let retry = cmp::min(self.dropped, self.sent.len());
+        self.stats.retried += retry as u64;
+        self.stats.lost += (self.dropped - retry) as u64;
+        for packet in self.sent.drain(..).rev().take(retry) {
+            self.queue.push_front(packet);
+        }
+        self.stats.max_queued = cmp::max(self.stats.max_queued, self.queue.len());
+        self.dropped = 0;
+    }
+    fn wait(&mut self, now: Instant) -> Option<Duration> {
+        if self.queue.is_empty() && self.dropped == 0 {
+            return None;
+        }
+        if let Some(until) = self.paused_until {
+            return Some(until.saturating_duration_since(now));
+        }
+        let bucket = match self.bucket {
+            Some(ref mut bucket) => bucket,
+            None => return Some(Duration::from_secs(0))
+        };
+        bucket.refill(now);
+        Some(bucket.wait(now))
+    }
+}
+
+/// A session that can only be written to through a `SendQueue`,
+/// so the queue knows about every packet sent and never sends an accepted one again
+pub struct QueuedSession<S, C: Clock = SystemClock> {
+    session: S,
+    queue: SendQueue<C>
+}
+
+impl<S, C: Clock> QueuedSession<S, C> {
+    /// Send everything written to `session` through `queue`
+    pub fn new(session: S, queue: SendQueue<C>) -> Self {
+        QueuedSession { session, queue }
+    }
+    /// Queue `packet` to be sent on the next `flush`
+    pub fn push(&mut self, packet: Packet) {
+        self.queue.push(packet);
+    }
+    /// Get the queue, such as for its stats
+    pub fn queue(&self) -> &SendQueue<C> {
+        &self.queue
+    }
+    /// Get the queue mutably, such as to ask for its `delay`
+    pub fn queue_mut(&mut self) -> &mut SendQueue<C> {
+        &mut self.queue
+    }
+    /// Get the session. It can't be written to, since the queue wouldn't know about it.
+    pub fn get_ref(&self) -> &S {
+        &self.session
+    }
+    /// Take the session and queue apart again
+    pub fn into_inner(self) -> (S, SendQueue<C>) {
+        (self.session, self.queue)
+    }
+}
+impl<T: Read + Write, C: Clock> QueuedSession<Session<T>, C> {
+    /// Read a packet and let the queue know about it
+    pub fn read(&mut self) -> Result<Packet, Error> {
+        let packet = self.session.read()?;
+        self.queue.update(&packet);
+        Ok(packet)
+    }
+    /// Read a packet if one is ready and let the queue know about it, see `Session::try_read`.
+    /// With a non-blocking session, call this and `flush` in a loop.
+    pub fn try_read(&mut self) -> Result<Option<Packet>, Error> {
+        let packet = self.session.try_read()?;
+        if let Some(ref packet) = packet {
+            self.queue.update(packet);
+        }
+        Ok(packet)
+    }
+    /// Write `packet` right away, skipping the queue but not its bookkeeping
+    pub fn write(&mut self, packet: &Packet) -> Result<(), Error> {
+        self.session.write(packet)?;
+        self.queue.record(packet.clone());
+        Ok(())
+    }
+    /// Write every queued packet that can be sent now
+    pub fn flush(&mut self) -> Result<(), Error> {
+        while let Some(packet) = self.queue.pop() {
+            self.session.write(&packet)?;
+        }
+        Ok(())
+    }
+}
+#[cfg(any(feature = "tokio", feature = "tokio-rustls"))]
+impl<T: AsyncRead + AsyncWrite + Unpin, C: Clock> QueuedSession<asynchronous::Session<T>, C> {
+    /// Read a packet and let the queue know about it
+    pub async fn read(&mut self) -> Result<Packet, Error> {
+        let packet = self.session.read().await?;
+        self.queue.update(&packet);
+        Ok(packet)
+    }
+    /// Write `packet` right away, skipping the queue but not its bookkeeping
+    pub async fn write(&mut self, packet: &Packet) -> Result<(), Error> {
+        self.session.write(packet).await?;
+        self.queue.record(packet.clone());
+        Ok(())
+    }
+    /// Write every queued packet that can be sent now
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        while let Some(packet) = self.queue.pop() {
+            self.session.write(&packet).await?;
+        }
+        Ok(())
+    }
+}
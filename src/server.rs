@@ -0,0 +1,651 @@
+use crate::blocking::Session;
+use crate::common::*;
+use crate::crypto;
+use crate::mode::Mode;
+use crate::testing::{self, Pipe};
+use failure::Error;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Account {
+    user: User,
+    // hex sha256 of the salt followed by the password
+    password: String,
+    salt: String,
+    token: String
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct Data {
+    users: BTreeMap<usize, Account>,
+    channels: BTreeMap<usize, Channel>,
+    messages: BTreeMap<usize, Message>,
+    last_id: usize
+}
+
+impl Data {
+    fn next_id(&mut self) -> usize {
+        self.last_id += 1;
+        self.last_id
+    }
+    fn account_by_name(&self, name: &str) -> Option<&Account> {
+        self.users.values().find(|account| account.user.name == name)
+    }
+    fn private_channel(&self, a: usize, b: usize) -> Option<&Channel> {
+        self.channels.values().find(|channel| {
+            channel.private
+                && [a, b].iter().all(|user| self.users[user].user.modes.contains_key(&channel.id))
+        })
+    }
+}
+
+struct Connection {
+    user: Option<usize>,
+    sender: Sender<Packet>,
+    // Closes the transport, if it can be closed from another thread
+    close: Option<Box<dyn Fn() + Send>>
+}
+
+#[derive(Default)]
+struct Inner {
+    data: Data,
+    path: Option<PathBuf>,
+    connections: HashMap<usize, Connection>,
+    last_connection: usize
+}
+
+/// A synac server that keeps everything in memory, for integration tests and as a reference
+/// of how the protocol works. It follows the same rules as the real server:
+///
+/// - Connecting with an unknown name creates an account, which requires a password.
+///   Logging in afterwards works with either the password or the token.
+/// - Only admins can create public channels, ban users or make them admins.
+///   Use `set_admin` to get the first admin.
+/// - Private channels are between two users, who both get read and write permission.
+///   Admins can't do anything in private channels they aren't part of.
+/// - Banned users can't do anything, and are disconnected as soon as they're banned.
+/// - Anything else is checked using the user's mode in the channel, or the channel's default mode for bots or users.
+/// - Message lists, bulk deletes and the lengths of names and messages are limited by the `LIMIT_*` constants.
+///
+/// Connections are plain, without TLS. Cloning a server gives another handle to the same one.
+/// Everything is kept in memory only, until `save` or `save_to` writes it to a file.
+#[derive(Clone, Default)]
+pub struct Server {
+    inner: Arc<Mutex<Inner>>
+}
+
+impl Server {
+    /// Create an empty server
+    pub fn new() -> Self {
+        Server::default()
+    }
+    /// Load a server from `path`, which `save` writes to.
+    /// A file that doesn't exist yet is treated as empty.
+    /// Changes aren't written back on their own, call `save` to keep them.
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Self, Error> {
+        let path = path.into();
+        let data = match fs::read(&path) {
+            Ok(contents) => rmps::from_slice(&contents)?,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Data::default(),
+            Err(err) => return Err(err.into())
+        };
+        Ok(Server {
+            inner: Arc::new(Mutex::new(Inner {
+                data,
+                path: Some(path),
+                ..Inner::default()
+            }))
+        })
+    }
+    /// Write everything except connections to the file this was opened from
+    pub fn save(&self) -> Result<(), Error> {
+        let path = self.lock().path.clone();
+        match path {
+            Some(path) => self.save_to(path),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "server has no file").into())
+        }
+    }
+    /// Write everything except connections to `path`
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let contents = rmps::to_vec(&self.lock().data)?;
+        Ok(fs::write(path, contents)?)
+    }
+
+    /// Get a user by id
+    pub fn user(&self, id: usize) -> Option<User> {
+        self.lock().data.users.get(&id).map(|account| account.user.clone())
+    }
+    /// Get a user by name
+    pub fn user_by_name(&self, name: &str) -> Option<User> {
+        self.lock().data.account_by_name(name).map(|account| account.user.clone())
+    }
+    /// Make a user admin or not, and tell everybody.
+    /// Returns false if there is no such user.
+    pub fn set_admin(&self, id: usize, admin: bool) -> bool {
+        let mut inner = self.lock();
+        let user = match inner.data.users.get_mut(&id) {
+            Some(account) => {
+                account.user.admin = admin;
+                account.user.clone()
+            },
+            None => return false
+        };
+        inner.broadcast(|_| true, &Packet::UserReceive(UserReceive { inner: user }));
+        true
+    }
+
+    /// Handle a client on another thread, reading from `reader` and writing to `writer`
+    /// until the client disconnects.
+    /// When the client gets banned, `writer` is dropped and `reader` once the next packet arrives.
+    pub fn serve<R, W>(&self, reader: R, writer: W)
+        where
+            R: Read + Send + 'static,
+            W: Write + Send + 'static
+    {
+        self.serve_closable(reader, writer, None);
+    }
+    fn serve_closable<R, W>(&self, mut reader: R, mut writer: W, close: Option<Box<dyn Fn() + Send>>)
+        where
+            R: Read + Send + 'static,
+            W: Write + Send + 'static
+    {
+        let (sender, receiver) = mpsc::channel::<Packet>();
+        let id = {
+            let mut inner = self.lock();
+            inner.last_connection += 1;
+            let id = inner.last_connection;
+            inner.connections.insert(id, Connection { user: None, sender, close });
+            id
+        };
+
+        // Stops once the connection is removed, since that drops the sender
+        thread::spawn(move || {
+            for packet in receiver {
+                if write(&mut writer, &packet).is_err() {
+                    break;
+                }
+            }
+        });
+        let server = self.clone();
+        thread::spawn(move || {
+            while let Ok(packet) = read(&mut reader) {
+                let mut inner = server.lock();
+                // disconnected by the server
+                if !inner.connections.contains_key(&id) {
+                    return;
+                }
+                inner.handle(id, packet);
+            }
+            server.lock().connections.remove(&id);
+        });
+    }
+    /// Connect a session in memory
+    pub fn connect(&self) -> Session<Pipe> {
+        let (client, server) = testing::pipe();
+        // a pipe can always be cloned
+        let handle = server.try_clone().unwrap();
+        self.serve_closable(server.try_clone().unwrap(), server, Some(Box::new(move || handle.shutdown())));
+        Session::from_stream(client)
+    }
+    /// Accept clients over plain TCP on another thread. Returns the address actually bound,
+    /// so a port of 0 can be used to pick a free one.
+    pub fn listen<A: ToSocketAddrs>(&self, addr: A) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let server = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue
+                };
+                if let (Ok(reader), Ok(handle)) = (stream.try_clone(), stream.try_clone()) {
+                    let close = move || { let _ = handle.shutdown(Shutdown::Both); };
+                    server.serve_closable(reader, stream, Some(Box::new(close)));
+                }
+            }
+        });
+        Ok(addr)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap()
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs() as i64).unwrap_or(0)
+}
+fn random_string() -> String {
+    let mut bytes = [0; 32];
+    crypto::random_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+fn hash_password(salt: &str, password: &str) -> String {
+    crypto::sha256(format!("{}{}", salt, password).as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+/// What `user` may do in `channel`.
+/// Written out on its own, so tests can compare `get_effective_mode` against it.
+fn mode(channel: &Channel, user: &User) -> Mode {
+    if user.ban {
+        return Mode::empty();
+    }
+    if user.admin && !channel.private {
+        return Mode::ALL;
+    }
+    let default = if channel.private {
+        0
+    } else if user.bot {
+        channel.default_mode_bot
+    } else {
+        channel.default_mode_user
+    };
+    Mode::from(user.modes.get(&channel.id).cloned().unwrap_or(default))
+}
+/// Whether `user` is told about `channel` at all
+fn can_see(channel: &Channel, user: &User) -> bool {
+    !channel.private || user.modes.contains_key(&channel.id)
+}
+fn check_name(name: &str, limit: usize) -> Result<(), u8> {
+    if name.is_empty() {
+        Err(ERR_MISSING_FIELD)
+    } else if name.len() > limit {
+        Err(ERR_LIMIT_REACHED)
+    } else {
+        Ok(())
+    }
+}
+
+impl Inner {
+    fn send(&self, connection: usize, packet: Packet) {
+        if let Some(connection) = self.connections.get(&connection) {
+            // the writer only stops once the connection is gone
+            let _ = connection.sender.send(packet);
+        }
+    }
+    /// Send `packet` to every logged in connection whose user `filter` accepts
+    fn broadcast<F: Fn(&User) -> bool>(&self, filter: F, packet: &Packet) {
+        for connection in self.connections.values() {
+            let user = connection.user.and_then(|id| self.data.users.get(&id));
+            if let Some(account) = user {
+                if filter(&account.user) {
+                    let _ = connection.sender.send(packet.clone());
+                }
+            }
+        }
+    }
+    /// Close every connection logged in as `user`
+    fn disconnect(&mut self, user: usize) {
+        let ids: Vec<usize> = self.connections.iter()
+            .filter(|&(_, connection)| connection.user == Some(user))
+            .map(|(&id, _)| id)
+            .collect();
+        for id in ids {
+            // dropping the sender stops the writer
+            if let Some(Connection { close: Some(close), .. }) = self.connections.remove(&id) {
+                close();
+            }
+        }
+    }
+    fn broadcast_user(&self, id: usize) {
+        let user = self.data.users[&id].user.clone();
+        self.broadcast(|_| true, &Packet::UserReceive(UserReceive { inner: user }));
+    }
+    fn channel(&self, id: usize) -> Result<Channel, u8> {
+        self.data.channels.get(&id).cloned().ok_or(ERR_UNKNOWN_CHANNEL)
+    }
+    fn message(&self, id: usize) -> Result<Message, u8> {
+        self.data.messages.get(&id).cloned().ok_or(ERR_UNKNOWN_MESSAGE)
+    }
+    fn require(&self, channel: &Channel, user: &User, required: Mode) -> Result<(), u8> {
+        if mode(channel, user).contains(required) {
+            Ok(())
+        } else {
+            Err(ERR_MISSING_PERMISSION)
+        }
+    }
+
+    fn handle(&mut self, connection: usize, packet: Packet) {
+        let user = self.connections.get(&connection)
+            .and_then(|connection| connection.user)
+            .and_then(|id| self.data.users.get(&id))
+            .map(|account| account.user.clone());
+        let result = match (packet, user) {
+            (Packet::Login(login), None) => self.login(connection, login),
+            (Packet::Login(_), Some(_)) => Err(ERR_ALREADY_EXISTS),
+            (_, None) => Err(ERR_MISSING_PERMISSION),
+            (packet, Some(user)) => self.handle_user(connection, user, packet)
+        };
+        if let Err(code) = result {
+            self.send(connection, Packet::Err(code));
+        }
+    }
+    fn login(&mut self, connection: usize, login: Login) -> Result<(), u8> {
+        check_name(&login.name, LIMIT_USER_NAME)?;
+        let (id, token, created) = match self.data.account_by_name(&login.name) {
+            Some(account) => {
+                if account.user.bot != login.bot {
+                    return Err(ERR_LOGIN_BOT);
+                }
+                if account.user.ban {
+                    return Err(ERR_LOGIN_BANNED);
+                }
+                let valid = match (login.token, login.password) {
+                    (Some(token), _) => token == account.token,
+                    (None, Some(password)) => hash_password(&account.salt, &password) == account.password,
+                    (None, None) => return Err(ERR_MISSING_FIELD)
+                };
+                if !valid {
+                    return Err(ERR_LOGIN_INVALID);
+                }
+                (account.user.id, account.token.clone(), false)
+            },
+            None => {
+                let password = match login.password {
+                    Some(password) => password,
+                    None if login.token.is_some() => return Err(ERR_LOGIN_INVALID),
+                    None => return Err(ERR_MISSING_FIELD)
+                };
+                let id = self.data.next_id();
+                let salt = random_string();
+                let account = Account {
+                    user: User {
+                        admin: false,
+                        ban: false,
+                        bot: login.bot,
+                        id,
+                        modes: HashMap::new(),
+                        name: login.name
+                    },
+                    password: hash_password(&salt, &password),
+                    salt,
+                    token: random_string()
+                };
+                let token = account.token.clone();
+                self.data.users.insert(id, account);
+                self.broadcast_user(id);
+                (id, token, true)
+            }
+        };
+
+        if let Some(connection) = self.connections.get_mut(&connection) {
+            connection.user = Some(id);
+        }
+        self.send(connection, Packet::LoginSuccess(LoginSuccess { created, id, token }));
+        let user = self.data.users[&id].user.clone();
+        for account in self.data.users.values() {
+            self.send(connection, Packet::UserReceive(UserReceive { inner: account.user.clone() }));
+        }
+        for channel in self.data.channels.values().filter(|channel| can_see(channel, &user)) {
+            self.send(connection, Packet::ChannelReceive(ChannelReceive { inner: channel.clone() }));
+        }
+        Ok(())
+    }
+    fn handle_user(&mut self, connection: usize, me: User, packet: Packet) -> Result<(), u8> {
+        match packet {
+            Packet::ChannelCreate(create) => match create.recipient {
+                Some(recipient) => {
+                    if recipient == me.id {
+                        return Err(ERR_SELF_PM);
+                    }
+                    if !self.data.users.contains_key(&recipient) {
+                        return Err(ERR_UNKNOWN_USER);
+                    }
+                    if self.data.private_channel(me.id, recipient).is_some() {
+                        return Err(ERR_ALREADY_EXISTS);
+                    }
+                    if create.name.len() > LIMIT_CHANNEL_NAME {
+                        return Err(ERR_LIMIT_REACHED);
+                    }
+                    let channel = Channel {
+                        default_mode_bot: 0,
+                        default_mode_user: 0,
+                        id: self.data.next_id(),
+                        name: create.name,
+                        private: true
+                    };
+                    for user in &[me.id, recipient] {
+                        let account = self.data.users.get_mut(user).unwrap();
+                        account.user.modes.insert(channel.id, PERM_READ | PERM_WRITE);
+                    }
+                    self.data.channels.insert(channel.id, channel.clone());
+                    self.broadcast_user(me.id);
+                    self.broadcast_user(recipient);
+                    self.broadcast(|user| can_see(&channel, user), &Packet::ChannelReceive(ChannelReceive { inner: channel.clone() }));
+                    Ok(())
+                },
+                None => {
+                    if !me.admin {
+                        return Err(ERR_MISSING_PERMISSION);
+                    }
+                    check_name(&create.name, LIMIT_CHANNEL_NAME)?;
+                    let channel = Channel {
+                        default_mode_bot: create.default_mode_bot,
+                        default_mode_user: create.default_mode_user,
+                        id: self.data.next_id(),
+                        name: create.name,
+                        private: false
+                    };
+                    self.data.channels.insert(channel.id, channel.clone());
+                    self.broadcast(|_| true, &Packet::ChannelReceive(ChannelReceive { inner: channel }));
+                    Ok(())
+                }
+            },
+            Packet::ChannelUpdate(update) => {
+                let old = self.channel(update.inner.id)?;
+                self.require(&old, &me, Mode::MANAGE_CHANNELS)?;
+                if old.private {
+                    return Err(ERR_MISSING_PERMISSION);
+                }
+                check_name(&update.inner.name, LIMIT_CHANNEL_NAME)?;
+                let channel = Channel {
+                    private: false,
+                    ..update.inner
+                };
+                self.data.channels.insert(channel.id, channel.clone());
+                self.broadcast(|_| true, &Packet::ChannelReceive(ChannelReceive { inner: channel }));
+                Ok(())
+            },
+            Packet::ChannelDelete(delete) => {
+                let channel = self.channel(delete.id)?;
+                self.require(&channel, &me, Mode::MANAGE_CHANNELS)?;
+                self.broadcast(|user| can_see(&channel, user), &Packet::ChannelDeleteReceive(ChannelDeleteReceive { inner: channel.clone() }));
+                self.data.channels.remove(&channel.id);
+                self.data.messages.retain(|_, message| message.channel != channel.id);
+                for account in self.data.users.values_mut() {
+                    account.user.modes.remove(&channel.id);
+                }
+                Ok(())
+            },
+            Packet::Command(command) => {
+                match self.data.users.get(&command.recipient) {
+                    Some(account) if account.user.bot => (),
+                    _ => return Err(ERR_UNKNOWN_BOT)
+                }
+                let recipient = command.recipient;
+                let packet = Packet::CommandReceive(CommandReceive {
+                    args: command.args,
+                    author: me.id
+                });
+                self.broadcast(|user| user.id == recipient, &packet);
+                Ok(())
+            },
+            Packet::LoginUpdate(update) => {
+                if let Some(ref name) = update.name {
+                    check_name(name, LIMIT_USER_NAME)?;
+                    if self.data.account_by_name(name).map(|account| account.user.id != me.id).unwrap_or(false) {
+                        return Err(ERR_ALREADY_EXISTS);
+                    }
+                }
+                let account = self.data.users.get_mut(&me.id).unwrap();
+                if let Some(ref password) = update.password_new {
+                    let current = update.password_current.as_ref().ok_or(ERR_MISSING_FIELD)?;
+                    if hash_password(&account.salt, current) != account.password {
+                        return Err(ERR_LOGIN_INVALID);
+                    }
+                    account.salt = random_string();
+                    account.password = hash_password(&account.salt, password);
+                }
+                if update.reset_token || update.password_new.is_some() {
+                    account.token = random_string();
+                }
+                let token = account.token.clone();
+                let renamed = update.name.is_some();
+                if let Some(name) = update.name {
+                    account.user.name = name;
+                }
+
+                self.send(connection, Packet::LoginSuccess(LoginSuccess { created: false, id: me.id, token }));
+                if renamed {
+                    self.broadcast_user(me.id);
+                }
+                Ok(())
+            },
+            Packet::MessageCreate(create) => {
+                let channel = self.channel(create.channel)?;
+                self.require(&channel, &me, Mode::WRITE)?;
+                if create.text.len() > LIMIT_MESSAGE {
+                    return Err(ERR_LIMIT_REACHED);
+                }
+                let message = Message {
+                    author: me.id,
+                    channel: channel.id,
+                    id: self.data.next_id(),
+                    text: create.text,
+                    timestamp: now(),
+                    timestamp_edit: None
+                };
+                self.data.messages.insert(message.id, message.clone());
+                self.broadcast(|user| mode(&channel, user).contains(Mode::READ),
+                    &Packet::MessageReceive(MessageReceive { inner: message, new: true }));
+                Ok(())
+            },
+            Packet::MessageUpdate(update) => {
+                let mut message = self.message(update.id)?;
+                let channel = self.channel(message.channel)?;
+                if message.author != me.id {
+                    return Err(ERR_MISSING_PERMISSION);
+                }
+                self.require(&channel, &me, Mode::WRITE)?;
+                if update.text.len() > LIMIT_MESSAGE {
+                    return Err(ERR_LIMIT_REACHED);
+                }
+                message.text = update.text;
+                message.timestamp_edit = Some(now());
+                self.data.messages.insert(message.id, message.clone());
+                self.broadcast(|user| mode(&channel, user).contains(Mode::READ),
+                    &Packet::MessageReceive(MessageReceive { inner: message, new: false }));
+                Ok(())
+            },
+            Packet::MessageDelete(delete) => {
+                let message = self.message(delete.id)?;
+                let channel = self.channel(message.channel)?;
+                if message.author != me.id {
+                    self.require(&channel, &me, Mode::MANAGE_MESSAGES)?;
+                }
+                self.data.messages.remove(&message.id);
+                self.broadcast(|user| mode(&channel, user).contains(Mode::READ),
+                    &Packet::MessageDeleteReceive(MessageDeleteReceive { id: message.id }));
+                Ok(())
+            },
+            Packet::MessageDeleteBulk(delete) => {
+                let channel = self.channel(delete.channel)?;
+                if delete.ids.len() > LIMIT_BULK {
+                    return Err(ERR_LIMIT_REACHED);
+                }
+                self.require(&channel, &me, Mode::MANAGE_MESSAGES)?;
+                // all or nothing
+                for id in &delete.ids {
+                    if self.message(*id)?.channel != channel.id {
+                        return Err(ERR_UNKNOWN_MESSAGE);
+                    }
+                }
+                for id in delete.ids {
+                    if self.data.messages.remove(&id).is_some() {
+                        self.broadcast(|user| mode(&channel, user).contains(Mode::READ),
+                            &Packet::MessageDeleteReceive(MessageDeleteReceive { id }));
+                    }
+                }
+                Ok(())
+            },
+            Packet::MessageList(list) => {
+                let channel = self.channel(list.channel)?;
+                self.require(&channel, &me, Mode::READ)?;
+                if list.limit > LIMIT_BULK {
+                    return Err(ERR_LIMIT_REACHED);
+                }
+                let matching = self.data.messages.values().filter(|message| {
+                    message.channel == channel.id
+                        && list.after.map(|after| message.id > after).unwrap_or(true)
+                        && list.before.map(|before| message.id < before).unwrap_or(true)
+                });
+                // the oldest messages after `after`, otherwise the newest ones
+                let mut messages: Vec<Message> = if list.after.is_some() {
+                    matching.take(list.limit).cloned().collect()
+                } else {
+                    matching.rev().take(list.limit).cloned().collect()
+                };
+                messages.sort_by_key(|message| message.id);
+
+                for message in messages {
+                    self.send(connection, Packet::MessageReceive(MessageReceive { inner: message, new: false }));
+                }
+                self.send(connection, Packet::MessageListReceived);
+                Ok(())
+            },
+            Packet::Typing(typing) => {
+                let channel = self.channel(typing.channel)?;
+                self.require(&channel, &me, Mode::WRITE)?;
+                let packet = Packet::TypingReceive(TypingReceive {
+                    author: me.id,
+                    channel: channel.id
+                });
+                self.broadcast(|user| user.id != me.id && mode(&channel, user).contains(Mode::READ), &packet);
+                Ok(())
+            },
+            Packet::UserUpdate(update) => {
+                if !self.data.users.contains_key(&update.id) {
+                    return Err(ERR_UNKNOWN_USER);
+                }
+                if (update.admin.is_some() || update.ban.is_some()) && !me.admin {
+                    return Err(ERR_MISSING_PERMISSION);
+                }
+                if let Some((channel, _)) = update.channel_mode {
+                    let channel = self.channel(channel)?;
+                    if channel.private {
+                        return Err(ERR_MISSING_PERMISSION);
+                    }
+                    self.require(&channel, &me, Mode::MANAGE_MODES)?;
+                }
+
+                let user = &mut self.data.users.get_mut(&update.id).unwrap().user;
+                if let Some(admin) = update.admin {
+                    user.admin = admin;
+                }
+                if let Some(ban) = update.ban {
+                    user.ban = ban;
+                }
+                match update.channel_mode {
+                    Some((channel, Some(mode))) => { user.modes.insert(channel, mode); },
+                    Some((channel, None)) => { user.modes.remove(&channel); },
+                    None => ()
+                }
+                self.broadcast_user(update.id);
+                if update.ban == Some(true) {
+                    self.disconnect(update.id);
+                }
+                Ok(())
+            },
+            // Packets only the server sends
+            _ => Ok(())
+        }
+    }
+}
@@ -1,17 +1,73 @@
-use common::*;
-use std::collections::HashMap;
+use crate::cache::MessageCache;
+use crate::common::*;
+use crate::get_effective_mode;
+use crate::mode::Mode;
+use std::collections::{BTreeSet, HashMap};
+
+/// What a packet changed about a channel or user, see `State::update_with_changes`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// A channel was created, or sent for the first time
+    ChannelCreated(usize),
+    /// A channel was deleted
+    ChannelDeleted(usize),
+    /// A channel's name changed from `old` to `new`
+    ChannelRenamed {
+        channel: usize,
+        old: String,
+        new: String
+    },
+    /// The mode bots (if `bot`) or users get in `channel` by default changed
+    ChannelDefaultModeChanged {
+        channel: usize,
+        bot: bool,
+        old: Mode,
+        new: Mode
+    },
+    /// A user was created, or sent for the first time
+    UserJoined(usize),
+    /// A user's name changed from `old` to `new`
+    UserRenamed {
+        user: usize,
+        old: String,
+        new: String
+    },
+    /// A user was banned
+    UserBanned(usize),
+    /// A user was unbanned
+    UserUnbanned(usize),
+    /// The mode `user` has in `channel` changed. `None` means the channel default.
+    UserModeChanged {
+        user: usize,
+        channel: usize,
+        old: Option<Mode>,
+        new: Option<Mode>
+    },
+    /// A user was made admin
+    AdminGranted(usize),
+    /// A user is no longer admin
+    AdminRevoked(usize)
+}
 
 /// A struct that remembers stuff previous packets have informed about
 pub struct State {
     pub channels: HashMap<usize, Channel>,
-    pub users:    HashMap<usize, User>
+    pub users:    HashMap<usize, User>,
+    pub message_cache: Option<MessageCache>,
+    /// Your own user id, from `LoginSuccess`
+    pub id:       Option<usize>,
+    /// Your login token, from `LoginSuccess`
+    pub token:    Option<String>
 }
 
 impl Default for State {
     fn default() -> Self {
         State {
             channels: HashMap::new(),
-            users:    HashMap::new()
+            users:    HashMap::new(),
+            message_cache: None,
+            id:       None,
+            token:    None
         }
     }
 }
@@ -20,15 +76,39 @@ impl State {
     pub fn new() -> Self {
         State::default()
     }
+    /// Create new state that also remembers up to `limit` messages per channel
+    pub fn with_message_cache(limit: usize) -> Self {
+        State {
+            message_cache: Some(MessageCache::new(limit)),
+            ..State::default()
+        }
+    }
     /// Update the state with `packet`
     pub fn update(&mut self, packet: &Packet) {
         match *packet {
             Packet::ChannelDeleteReceive(ref event) => {
                 self.channels.remove(&event.inner.id);
+                if let Some(ref mut cache) = self.message_cache {
+                    cache.remove_channel(event.inner.id);
+                }
             },
             Packet::ChannelReceive(ref event) => {
                 self.channels.insert(event.inner.id, event.inner.clone());
             },
+            Packet::LoginSuccess(ref event) => {
+                self.id = Some(event.id);
+                self.token = Some(event.token.clone());
+            },
+            Packet::MessageDeleteReceive(ref event) => {
+                if let Some(ref mut cache) = self.message_cache {
+                    cache.remove(event.id);
+                }
+            },
+            Packet::MessageReceive(ref event) => {
+                if let Some(ref mut cache) = self.message_cache {
+                    cache.insert(&event.inner);
+                }
+            },
             Packet::UserReceive(ref event) => {
                 self.users.insert(event.inner.id, event.inner.clone());
             },
@@ -36,6 +116,129 @@ impl State {
         }
     }
 
+    /// Update the state with `packet`, returning what it changed compared to the previous entry.
+    /// Only channel and user packets cause changes.
+    pub fn update_with_changes(&mut self, packet: &Packet) -> Vec<Change> {
+        let changes = self.changes(packet);
+        self.update(packet);
+        changes
+    }
+    fn changes(&self, packet: &Packet) -> Vec<Change> {
+        let mut changes = Vec::new();
+        match *packet {
+            Packet::ChannelDeleteReceive(ref event) if self.channels.contains_key(&event.inner.id) => {
+                changes.push(Change::ChannelDeleted(event.inner.id));
+            },
+            Packet::ChannelReceive(ref event) => {
+                let new = &event.inner;
+                let old = match self.channels.get(&new.id) {
+                    Some(old) => old,
+                    None => return vec![Change::ChannelCreated(new.id)]
+                };
+                if old.name != new.name {
+                    changes.push(Change::ChannelRenamed {
+                        channel: new.id,
+                        old: old.name.clone(),
+                        new: new.name.clone()
+                    });
+                }
+                for &(bot, old, new) in &[(true, old.default_mode_bot, new.default_mode_bot),
+                                          (false, old.default_mode_user, new.default_mode_user)] {
+                    if old != new {
+                        changes.push(Change::ChannelDefaultModeChanged {
+                            channel: event.inner.id,
+                            bot,
+                            old: Mode::from(old),
+                            new: Mode::from(new)
+                        });
+                    }
+                }
+            },
+            Packet::UserReceive(ref event) => {
+                let new = &event.inner;
+                let old = match self.users.get(&new.id) {
+                    Some(old) => old,
+                    None => return vec![Change::UserJoined(new.id)]
+                };
+                if old.name != new.name {
+                    changes.push(Change::UserRenamed {
+                        user: new.id,
+                        old: old.name.clone(),
+                        new: new.name.clone()
+                    });
+                }
+                match (old.ban, new.ban) {
+                    (false, true) => changes.push(Change::UserBanned(new.id)),
+                    (true, false) => changes.push(Change::UserUnbanned(new.id)),
+                    _ => ()
+                }
+                match (old.admin, new.admin) {
+                    (false, true) => changes.push(Change::AdminGranted(new.id)),
+                    (true, false) => changes.push(Change::AdminRevoked(new.id)),
+                    _ => ()
+                }
+                let channels: BTreeSet<_> = old.modes.keys().chain(new.modes.keys()).collect();
+                for &channel in channels {
+                    let (old, new) = (old.modes.get(&channel), new.modes.get(&channel));
+                    if old != new {
+                        changes.push(Change::UserModeChanged {
+                            user: event.inner.id,
+                            channel,
+                            old: old.cloned().map(Mode::from),
+                            new: new.cloned().map(Mode::from)
+                        });
+                    }
+                }
+            },
+            _ => ()
+        }
+        changes
+    }
+
+    /// Get your own user, once both `LoginSuccess` and its `UserReceive` have been received
+    pub fn me(&self) -> Option<&User> {
+        self.id.and_then(|id| self.users.get(&id))
+    }
+    /// Get the mode you actually have in `channel`
+    pub fn my_mode(&self, channel: usize) -> Option<Mode> {
+        self.id.and_then(|id| self.effective_mode(id, channel))
+    }
+    /// Check if you're an admin
+    pub fn is_admin(&self) -> bool {
+        self.me().map(|me| me.admin).unwrap_or(false)
+    }
+
+    /// Get a remembered message by id.
+    /// Call this before `update` to find out which channel a deleted message was in.
+    pub fn message(&self, id: usize) -> Option<&Message> {
+        self.message_cache.as_ref().and_then(|cache| cache.message(id))
+    }
+    /// Get all remembered messages in `channel`, oldest first.
+    /// Empty unless the message cache is enabled.
+    pub fn messages(&self, channel: usize) -> Vec<&Message> {
+        self.message_cache.as_ref()
+            .map(|cache| cache.messages(channel).collect())
+            .unwrap_or_default()
+    }
+    /// Get the most recent remembered message in `channel`
+    pub fn last_message(&self, channel: usize) -> Option<&Message> {
+        self.message_cache.as_ref().and_then(|cache| cache.last_message(channel))
+    }
+
+    /// Get the mode `user` actually has in `channel`, see `get_effective_mode`.
+    /// Returns `None` if either the user or the channel is unknown.
+    pub fn effective_mode(&self, user: usize, channel: usize) -> Option<Mode> {
+        let user = self.users.get(&user)?;
+        let channel = self.channels.get(&channel)?;
+        Some(get_effective_mode(channel, user))
+    }
+    /// Check if `user` has all permissions in `perm` in `channel`.
+    /// Useful to check if a request will succeed before sending it,
+    /// for example `Mode::MANAGE_MESSAGES` for `MessageDeleteBulk`.
+    pub fn can(&self, user: usize, channel: usize, perm: Mode) -> bool {
+        self.effective_mode(user, channel).map(|mode| mode.contains(perm)).unwrap_or(false)
+    }
+
     /// Search for a private channel with user
     pub fn get_private_channel<'a>(&'a self, user: &User) -> Option<&'a Channel> {
         user.modes.keys()
@@ -46,7 +249,7 @@ impl State {
 
     /// Search for the recipient in a private channel
     pub fn get_recipient(&self, channel: &Channel, me: usize) -> Option<&User> {
-        if channel.private { return None; }
+        if !channel.private { return None; }
         self.get_recipient_unchecked(channel.id, me)
     }
 
@@ -60,4 +263,31 @@ impl State {
                 (**user).modes.keys()
                 .any(|channel| *channel == channel_id))
     }
+
+    /// Get the private channel you have with `user`, if any
+    pub fn dm_channel(&self, user: usize) -> Option<&Channel> {
+        if self.id == Some(user) { return None; }
+        self.users.get(&user).and_then(|user| self.get_private_channel(user))
+    }
+    /// Get the other user in the private channel `channel`
+    pub fn dm_partner(&self, channel: usize) -> Option<&User> {
+        let me = self.id?;
+        let channel = self.channels.get(&channel)?;
+        self.get_recipient(channel, me)
+    }
+    /// List all your private channels along with the other user, sorted by channel id
+    pub fn list_dms(&self) -> Vec<(&Channel, Option<&User>)> {
+        let mut dms: Vec<_> = self.channels.values()
+            .filter(|channel| channel.private)
+            .map(|channel| (channel, self.id.and_then(|me| self.get_recipient_unchecked(channel.id, me))))
+            .collect();
+        dms.sort_by_key(|&(channel, _)| channel.id);
+        dms
+    }
+    /// List all known bots, sorted by user id
+    pub fn bots(&self) -> Vec<&User> {
+        let mut bots: Vec<_> = self.users.values().filter(|user| user.bot).collect();
+        bots.sort_by_key(|user| user.id);
+        bots
+    }
 }
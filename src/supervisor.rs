@@ -0,0 +1,234 @@
+use crate::common::Packet;
+use crate::error::{RateLimited, ServerError};
+use crate::known_hosts::HostKeyChanged;
+use crate::state::State;
+use crate::blocking::{Session, Stream};
+use crate::builder::MissingServerName;
+use crate::LoginOutcome;
+use failure::Error;
+#[cfg(feature = "openssl")] use openssl::x509::X509StoreContextRef;
+#[cfg(feature = "openssl")] use std::any::Any;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::ToSocketAddrs;
+#[cfg(feature = "openssl")] use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Returned when trying to write while there's no connection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NotConnected;
+
+impl fmt::Display for NotConnected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "not connected")
+    }
+}
+impl StdError for NotConnected {}
+
+/// Something that happened to a supervised connection
+#[derive(Debug)]
+pub enum Event {
+    /// A connection was established and logged in.
+    /// The state has been reset and will be rebuilt from the following packets.
+    Connected(LoginOutcome),
+    /// The connection was lost, or an attempt to connect failed
+    Disconnected(Error),
+    /// Waiting `delay` before connection attempt number `attempt`
+    Reconnecting {
+        attempt: u32,
+        delay: Duration
+    },
+    /// A packet was received. The state has already been updated with it.
+    Packet(Packet)
+}
+
+/// Keeps a session alive by reconnecting and logging in again
+/// with exponential backoff whenever the connection dies.
+/// `T` is the transport of the sessions, see `with_connector`.
+pub struct Supervisor<T = Stream> {
+    connect: Box<dyn FnMut() -> Result<Session<T>, Error>>,
+    bot: bool,
+    name: String,
+    token: String,
+
+    session: Option<Session<T>>,
+    state: State,
+
+    attempt: u32,
+    delay: Option<Duration>,
+    rate_limit: Option<Duration>,
+    min_delay: Duration,
+    max_delay: Duration,
+    seed: u64
+}
+
+impl Supervisor {
+    /// Create a supervisor that connects using `Session::new`.
+    /// `addr` is resolved again on every attempt, so a server that moved is found.
+    pub fn new<A, S>(addr: A, hash: S, bot: bool, name: S, token: S) -> Result<Supervisor, Error>
+        where
+            A: ToSocketAddrs + 'static,
+            S: Into<String>
+    {
+        let hash = hash.into();
+        Ok(Self::with_connector(move || Session::new(&addr, hash.clone()), bot, name, token))
+    }
+    #[cfg(feature = "openssl")]
+    /// Create a supervisor that connects using `Session::new_with_verify_callback`.
+    /// `addr` is resolved again on every attempt, so a server that moved is found.
+    pub fn new_with_verify_callback<A, F, S>(addr: A, callback: F, bot: bool, name: S, token: S)
+        -> Result<Supervisor, Error>
+        where
+            A: ToSocketAddrs + 'static,
+            F: Fn(bool, &mut X509StoreContextRef) -> bool + Any + 'static + Sync + Send,
+            S: Into<String>
+    {
+        let callback = Arc::new(callback);
+        Ok(Self::with_connector(move || {
+            let callback = Arc::clone(&callback);
+            Session::new_with_verify_callback(&addr, move |preverify, cert| callback(preverify, cert))
+        }, bot, name, token))
+    }
+}
+impl<T: Read + Write> Supervisor<T> {
+    /// Create a supervisor that calls `connect` to get a new session
+    pub fn with_connector<F, S>(connect: F, bot: bool, name: S, token: S) -> Supervisor<T>
+        where
+            F: FnMut() -> Result<Session<T>, Error> + 'static,
+            S: Into<String>
+    {
+        Supervisor {
+            connect: Box::new(connect),
+            bot,
+            name: name.into(),
+            token: token.into(),
+
+            session: None,
+            state: State::new(),
+
+            attempt: 0,
+            delay: None,
+            rate_limit: None,
+            min_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            seed: SystemTime::now().duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs() ^ u64::from(time.subsec_nanos()))
+                .unwrap_or(0) | 1
+        }
+    }
+
+    /// Set the delay before the first reconnection attempt, and the maximum delay.
+    /// Defaults to 1 second and 60 seconds.
+    pub fn set_backoff(&mut self, min: Duration, max: Duration) {
+        self.min_delay = min;
+        self.max_delay = max;
+    }
+
+    /// Returns the state, as built from the current connection
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+    /// Returns the current session, if connected
+    pub fn session(&mut self) -> Option<&mut Session<T>> {
+        self.session.as_mut()
+    }
+    /// Returns the token used to log in, as last received from the server
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Transmit a packet over the current connection.
+    /// Fails with `NotConnected` while reconnecting.
+    /// A failed write is reported as `Disconnected` by the next call to `next_event`.
+    pub fn write(&mut self, packet: &Packet) -> Result<(), Error> {
+        match self.session {
+            Some(ref mut session) => session.write(packet),
+            None => Err(NotConnected.into())
+        }
+    }
+
+    /// Block until something happens, connecting if necessary.
+    /// Returns an error only if reconnecting can't help, such as invalid credentials.
+    pub fn next_event(&mut self) -> Result<Event, Error> {
+        if self.session.is_none() {
+            if let Some(delay) = self.delay.take() {
+                thread::sleep(delay);
+            } else if self.attempt > 0 {
+                let delay = self.backoff();
+                self.delay = Some(delay);
+                return Ok(Event::Reconnecting {
+                    attempt: self.attempt,
+                    delay
+                });
+            }
+            return self.connect();
+        }
+
+        let result = self.session.as_mut().unwrap().read();
+        match result {
+            Ok(packet) => {
+                self.state.update(&packet);
+                Ok(Event::Packet(packet))
+            },
+            Err(err) => {
+                self.session = None;
+                self.attempt = 1;
+                Ok(Event::Disconnected(err))
+            }
+        }
+    }
+
+    fn connect(&mut self) -> Result<Event, Error> {
+        let (bot, name, token) = (self.bot, self.name.clone(), self.token.clone());
+        let result = (self.connect)().and_then(|mut session| {
+            let outcome = session.login(bot, name, token)?;
+            Ok((session, outcome))
+        });
+        match result {
+            Ok((session, outcome)) => {
+                self.token = outcome.token.clone();
+                self.state = State::new();
+                self.session = Some(session);
+                self.attempt = 0;
+                Ok(Event::Connected(outcome))
+            },
+            Err(err) => {
+                if err.downcast_ref::<HostKeyChanged>().is_some() || err.downcast_ref::<MissingServerName>().is_some() {
+                    return Err(err);
+                }
+                match err.downcast_ref::<ServerError>() {
+                    Some(&ServerError::MaxConnPerIp) | None => (),
+                    Some(_) => return Err(err)
+                }
+                if let Some(&RateLimited(seconds)) = err.downcast_ref::<RateLimited>() {
+                    self.rate_limit = Some(Duration::from_secs(seconds));
+                }
+                self.attempt += 1;
+                Ok(Event::Disconnected(err))
+            }
+        }
+    }
+
+    fn backoff(&mut self) -> Duration {
+        let exponent = (self.attempt - 1).min(16);
+        let max = self.min_delay.checked_mul(1 << exponent)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+
+        // xorshift, good enough for jitter
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+
+        // somewhere between half and all of the delay
+        let jitter = (self.seed % 1000) as u32;
+        let delay = max / 2 + max / 2 / 1000 * jitter;
+
+        match self.rate_limit.take() {
+            Some(rate_limit) => delay.max(rate_limit),
+            None => delay
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use crate::blocking::Session;
+use crate::common::{self, LoginSuccess, Packet};
+use failure::Error;
+use std::collections::VecDeque;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::panic;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Returned by a `MockServer` when the client didn't do what the script expected
+#[derive(Debug)]
+pub enum MockError {
+    /// The client sent `got` when the script expected `expected`
+    Unexpected { expected: String, got: Packet },
+    /// The client disconnected while the script still expected `expected`
+    Disconnected { expected: String },
+    /// The client sent a packet after the script was done
+    Extra(Packet)
+}
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MockError::Unexpected { ref expected, ref got } => write!(f, "expected {}, got {:?}", expected, got),
+            MockError::Disconnected { ref expected } => write!(f, "expected {}, but the client disconnected", expected),
+            MockError::Extra(ref packet) => write!(f, "unexpected {:?} after the script ended", packet)
+        }
+    }
+}
+impl StdError for MockError {}
+
+#[derive(Default)]
+struct Buffer {
+    data: VecDeque<u8>,
+    closed: bool
+}
+#[derive(Default)]
+struct Channel {
+    buffer: Mutex<Buffer>,
+    ready: Condvar
+}
+impl Channel {
+    fn close(&self) {
+        self.buffer.lock().unwrap().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+// Both channels of one end, closed once every handle to the end is dropped
+struct Ends {
+    read: Arc<Channel>,
+    write: Arc<Channel>
+}
+impl Drop for Ends {
+    fn drop(&mut self) {
+        self.read.close();
+        self.write.close();
+    }
+}
+
+/// One end of an in-memory connection, see `pipe`
+pub struct Pipe {
+    ends: Arc<Ends>
+}
+
+/// Create two connected in-memory ends.
+/// Reads block until the other end writes something, and return EOF once it's dropped.
+pub fn pipe() -> (Pipe, Pipe) {
+    let (a, b) = (Arc::new(Channel::default()), Arc::new(Channel::default()));
+    (
+        Pipe { ends: Arc::new(Ends { read: Arc::clone(&a), write: Arc::clone(&b) }) },
+        Pipe { ends: Arc::new(Ends { read: b, write: a }) }
+    )
+}
+
+impl Pipe {
+    /// Get another handle to the same end, like `TcpStream::try_clone`.
+    /// The end is closed once all handles are dropped.
+    pub fn try_clone(&self) -> io::Result<Pipe> {
+        Ok(Pipe { ends: Arc::clone(&self.ends) })
+    }
+    /// Close this end for all handles, like `TcpStream::shutdown`.
+    /// The other end can still read what was already written.
+    pub fn shutdown(&self) {
+        self.ends.read.close();
+        self.ends.write.close();
+    }
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let channel = &self.ends.read;
+        let mut buffer = channel.buffer.lock().unwrap();
+        while buffer.data.is_empty() && !buffer.closed {
+            buffer = channel.ready.wait(buffer).unwrap();
+        }
+        let len = buf.len().min(buffer.data.len());
+        for (dst, src) in buf.iter_mut().zip(buffer.data.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let channel = &self.ends.write;
+        let mut buffer = channel.buffer.lock().unwrap();
+        if buffer.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        buffer.data.extend(buf);
+        channel.ready.notify_all();
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Step {
+    Expect {
+        description: String,
+        matcher: Box<dyn Fn(&Packet) -> bool + Send>
+    },
+    Reply(Packet)
+}
+
+/// A fake synac server that follows a script, for testing code written against `Session`.
+/// Each packet the client sends has to be the next one the script expects.
+#[derive(Default)]
+pub struct MockServer {
+    steps: VecDeque<Step>
+}
+
+impl MockServer {
+    /// Create a server with an empty script
+    pub fn new() -> Self {
+        MockServer::default()
+    }
+
+    /// Expect the client to send `packet` next.
+    /// Packets are compared by their encoding, so use `expect_matching` for ones containing maps.
+    pub fn expect(self, packet: Packet) -> Self {
+        let encoded = common::serialize(&packet).ok();
+        self.expect_matching(format!("{:?}", packet), move |got| common::serialize(got).ok() == encoded)
+    }
+    /// Expect the client to send a packet that `matcher` accepts next
+    pub fn expect_matching<S, F>(mut self, description: S, matcher: F) -> Self
+        where
+            S: Into<String>,
+            F: Fn(&Packet) -> bool + Send + 'static
+    {
+        self.steps.push_back(Step::Expect {
+            description: description.into(),
+            matcher: Box::new(matcher)
+        });
+        self
+    }
+    /// Send `packet` to the client
+    pub fn reply(mut self, packet: Packet) -> Self {
+        self.steps.push_back(Step::Reply(packet));
+        self
+    }
+    /// Expect any login and accept it as user `id`
+    pub fn login(self, id: usize) -> Self {
+        self.expect_matching("Login", |packet| matches!(*packet, Packet::Login(_)))
+            .reply(Packet::LoginSuccess(LoginSuccess {
+                created: false,
+                id,
+                token: String::from("token")
+            }))
+    }
+
+    /// Follow the script over `transport`, which can be wrapped in TLS by the caller.
+    /// Once the script ends, waits for the client to disconnect and fails if it sends anything else.
+    pub fn run<T: Read + Write>(self, mut transport: T) -> Result<(), Error> {
+        for step in self.steps {
+            match step {
+                Step::Reply(packet) => common::write(&mut transport, &packet)?,
+                Step::Expect { description, matcher } => match common::read(&mut transport) {
+                    Ok(ref packet) if matcher(packet) => (),
+                    Ok(packet) => return Err(MockError::Unexpected { expected: description, got: packet }.into()),
+                    Err(common::Error::IoError(ref err)) if disconnected(err) =>
+                        return Err(MockError::Disconnected { expected: description }.into()),
+                    Err(err) => return Err(err.into())
+                }
+            }
+        }
+        match common::read(&mut transport) {
+            Ok(packet) => Err(MockError::Extra(packet).into()),
+            Err(common::Error::IoError(ref err)) if disconnected(err) => Ok(()),
+            Err(err) => Err(err.into())
+        }
+    }
+    /// Run the script on another thread, connected to the returned session in memory
+    pub fn spawn(self) -> (Session<Pipe>, MockHandle) {
+        let (client, server) = pipe();
+        let thread = thread::spawn(move || self.run(server));
+        (Session::from_stream(client), MockHandle { thread })
+    }
+    /// Run the script on another thread for the first client that connects to the returned address,
+    /// using plain TCP. Useful for the async session.
+    pub fn bind(self) -> io::Result<(SocketAddr, MockHandle)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let thread = thread::spawn(move || {
+            let (stream, _) = listener.accept()?;
+            self.run(stream)
+        });
+        Ok((addr, MockHandle { thread }))
+    }
+}
+
+fn disconnected(err: &io::Error) -> bool {
+    matches!(err.kind(), io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset | io::ErrorKind::BrokenPipe)
+}
+
+/// A `MockServer` running on another thread
+pub struct MockHandle {
+    thread: JoinHandle<Result<(), Error>>
+}
+
+impl MockHandle {
+    /// Wait for the script to finish and return whether the client followed it.
+    /// Drop the session first, since the server waits for it to disconnect.
+    pub fn finish(self) -> Result<(), Error> {
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(err) => panic::resume_unwind(err)
+        }
+    }
+}
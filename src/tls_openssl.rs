@@ -0,0 +1,51 @@
+use crate::builder::{SessionBuilder, TlsVersion, Verification};
+use failure::Error;
+use openssl::ssl::{ConnectConfiguration, SslConnector, SslMethod, SslRef, SslVerifyMode, SslVersion};
+use crate::fingerprint::Fingerprint;
+use openssl::x509::{X509Ref, X509StoreContextRef};
+
+/// Get the fingerprint of the public key of `cert`
+fn fingerprint(cert: &X509Ref) -> Option<Fingerprint> {
+    let der = cert.public_key().ok()?.public_key_to_der().ok()?;
+    Some(Fingerprint::from_public_key_der(&der))
+}
+
+/// Returns a verify callback that accepts any certificate whose public key matches a hash
+pub(crate) fn pinned(hash: String) -> impl Fn(bool, &mut X509StoreContextRef) -> bool + 'static + Sync + Send {
+    move |_, cert| {
+        cert.current_cert()
+            .and_then(fingerprint)
+            .map(|fingerprint| fingerprint.matches(&hash))
+            .unwrap_or(false)
+    }
+}
+/// Get the fingerprint of the public key the server used
+pub(crate) fn peer_fingerprint(ssl: &SslRef) -> Option<Fingerprint> {
+    ssl.peer_certificate().and_then(|cert| fingerprint(&cert))
+}
+
+/// Set up an openssl connection the way `options` says
+pub(crate) fn configure(options: &SessionBuilder) -> Result<ConnectConfiguration, Error> {
+    let mut config = SslConnector::builder(SslMethod::tls())?;
+    match options.verification {
+        Verification::Pinned(ref hash) => config.set_verify_callback(SslVerifyMode::PEER, pinned(hash.clone())),
+        // checked against the file after the handshake
+        Verification::KnownHosts(_) | Verification::None => config.set_verify_callback(SslVerifyMode::PEER, |_, _| true),
+        Verification::Ca => {
+            for path in &options.ca_files {
+                config.set_ca_file(path)?;
+            }
+        }
+    }
+    let version = match options.min_tls_version.unwrap_or(TlsVersion::Tls12) {
+        TlsVersion::Tls12 => SslVersion::TLS1_2,
+        TlsVersion::Tls13 => SslVersion::TLS1_3
+    };
+    config.set_min_proto_version(Some(version))?;
+
+    let named = options.server_name.is_some();
+    let mut config = config.build().configure()?;
+    config.set_use_server_name_indication(options.sni && named);
+    config.set_verify_hostname(named);
+    Ok(config)
+}
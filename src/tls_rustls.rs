@@ -0,0 +1,102 @@
+use crate::builder::{SessionBuilder, TlsVersion, Verification};
+use crate::fingerprint::Fingerprint;
+use failure::Error;
+use rustls::{CertificateError, ClientConfig, CommonState, DigitallySignedStruct, RootCertStore, SignatureScheme, SupportedProtocolVersion};
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{self, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::pki_types::pem::PemObject;
+use rustls::version;
+use std::convert::TryFrom;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Get the fingerprint of the public key of `cert`
+fn fingerprint(cert: &CertificateDer<'_>) -> Result<Fingerprint, rustls::Error> {
+    Fingerprint::from_cert_der(cert)
+        .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))
+}
+/// Get the fingerprint of the public key the server used
+pub(crate) fn peer_fingerprint(conn: &CommonState) -> Option<Fingerprint> {
+    fingerprint(conn.peer_certificates()?.first()?).ok()
+}
+
+/// Accepts any certificate whose public key matches a hash,
+/// just like `Session::new` does with openssl.
+/// Without a hash, any key is accepted and has to be checked after the handshake.
+#[derive(Debug)]
+struct PinnedKey {
+    hash: Option<String>,
+    provider: Arc<CryptoProvider>
+}
+
+impl ServerCertVerifier for PinnedKey {
+    fn verify_server_cert(&self, end_entity: &CertificateDer<'_>, _: &[CertificateDer<'_>],
+                          _: &ServerName<'_>, _: &[u8], _: UnixTime)
+        -> Result<ServerCertVerified, rustls::Error>
+    {
+        let fingerprint = fingerprint(end_entity)?;
+        match self.hash {
+            Some(ref hash) if !fingerprint.matches(hash) =>
+                Err(rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure)),
+            _ => Ok(ServerCertVerified::assertion())
+        }
+    }
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct)
+        -> Result<HandshakeSignatureValid, rustls::Error>
+    {
+        crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct)
+        -> Result<HandshakeSignatureValid, rustls::Error>
+    {
+        crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Create a client config the way `options` says
+pub(crate) fn configure(options: &SessionBuilder) -> Result<Arc<ClientConfig>, Error> {
+    let provider = Arc::new(crypto::ring::default_provider());
+    let versions: &[&SupportedProtocolVersion] = match options.min_tls_version.unwrap_or(TlsVersion::Tls12) {
+        TlsVersion::Tls12 => &[&version::TLS13, &version::TLS12],
+        TlsVersion::Tls13 => &[&version::TLS13]
+    };
+    let builder = ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_protocol_versions(versions)?;
+
+    let verifier: Arc<dyn ServerCertVerifier> = match options.verification {
+        Verification::Pinned(ref hash) => Arc::new(PinnedKey { hash: Some(hash.clone()), provider }),
+        // checked against the file after the handshake
+        Verification::KnownHosts(_) | Verification::None => Arc::new(PinnedKey { hash: None, provider }),
+        Verification::Ca => {
+            let mut roots = RootCertStore::empty();
+            roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+            for path in &options.ca_files {
+                for cert in CertificateDer::pem_file_iter(path)? {
+                    roots.add(cert?)?;
+                }
+            }
+            WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider).build()?
+        }
+    };
+
+    let mut config = builder
+        .dangerous()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth();
+    config.enable_sni = options.sni;
+    Ok(Arc::new(config))
+}
+
+/// The name to connect with. Without a server name, use the peer's address,
+/// which also makes rustls skip SNI.
+pub(crate) fn server_name(options: &SessionBuilder, ip: IpAddr) -> Result<ServerName<'static>, Error> {
+    match options.server_name {
+        Some(ref name) => Ok(ServerName::try_from(name.clone())?),
+        None => Ok(ServerName::IpAddress(ip.into()))
+    }
+}
@@ -0,0 +1,151 @@
+use crate::common::{self, Packet};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A source of the current time.
+/// Implemented for any `Fn() -> Instant`, so tests can control time.
+pub trait Clock {
+    /// Returns the current time
+    fn now(&self) -> Instant;
+}
+impl<F: Fn() -> Instant> Clock for F {
+    fn now(&self) -> Instant {
+        self()
+    }
+}
+
+/// The system clock, see `Instant::now`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// How long a typing indicator lasts
+pub fn typing_timeout() -> Duration {
+    Duration::from_secs(u64::from(common::TYPING_TIMEOUT))
+}
+
+/// Remembers who is typing where, forgetting them after `TYPING_TIMEOUT` seconds
+pub struct TypingTracker<C: Clock = SystemClock> {
+    clock: C,
+    typing: HashMap<usize, HashMap<usize, Instant>>
+}
+
+impl Default for TypingTracker<SystemClock> {
+    fn default() -> Self {
+        TypingTracker::with_clock(SystemClock)
+    }
+}
+impl TypingTracker<SystemClock> {
+    /// Create a new typing tracker using the system clock
+    pub fn new() -> Self {
+        TypingTracker::default()
+    }
+}
+impl<C: Clock> TypingTracker<C> {
+    /// Create a new typing tracker using a custom clock
+    pub fn with_clock(clock: C) -> Self {
+        TypingTracker {
+            clock,
+            typing: HashMap::new()
+        }
+    }
+    /// Update the tracker with `packet`.
+    /// Besides typing events, a new message stops its author from typing.
+    pub fn update(&mut self, packet: &Packet) {
+        match *packet {
+            Packet::ChannelDeleteReceive(ref event) => {
+                self.typing.remove(&event.inner.id);
+            },
+            Packet::MessageReceive(ref event) if event.new => {
+                if let Some(authors) = self.typing.get_mut(&event.inner.channel) {
+                    authors.remove(&event.inner.author);
+                }
+            },
+            Packet::TypingReceive(ref event) => {
+                let now = self.clock.now();
+                self.typing.entry(event.channel).or_default().insert(event.author, now);
+            },
+            _ => ()
+        }
+    }
+    /// Forget all typing indicators that have timed out
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        for authors in self.typing.values_mut() {
+            authors.retain(|_, since| now.duration_since(*since) < typing_timeout());
+        }
+        self.typing.retain(|_, authors| !authors.is_empty());
+    }
+
+    /// Check if `author` is typing in `channel`
+    pub fn is_typing(&self, author: usize, channel: usize) -> bool {
+        let now = self.clock.now();
+        self.typing.get(&channel)
+            .and_then(|authors| authors.get(&author))
+            .map(|since| now.duration_since(*since) < typing_timeout())
+            .unwrap_or(false)
+    }
+    /// Get all users typing in `channel`, sorted by id
+    pub fn typing_in(&self, channel: usize) -> Vec<usize> {
+        let now = self.clock.now();
+        let mut authors: Vec<usize> = self.typing.get(&channel)
+            .map(|authors| authors.iter()
+                .filter(|&(_, since)| now.duration_since(*since) < typing_timeout())
+                .map(|(author, _)| *author)
+                .collect())
+            .unwrap_or_default();
+        authors.sort();
+        authors
+    }
+}
+
+/// Makes sure your own typing indicators are only sent once per `TYPING_TIMEOUT` seconds
+pub struct TypingThrottle<C: Clock = SystemClock> {
+    clock: C,
+    sent: HashMap<usize, Instant>
+}
+
+impl Default for TypingThrottle<SystemClock> {
+    fn default() -> Self {
+        TypingThrottle::with_clock(SystemClock)
+    }
+}
+impl TypingThrottle<SystemClock> {
+    /// Create a new typing throttle using the system clock
+    pub fn new() -> Self {
+        TypingThrottle::default()
+    }
+}
+impl<C: Clock> TypingThrottle<C> {
+    /// Create a new typing throttle using a custom clock
+    pub fn with_clock(clock: C) -> Self {
+        TypingThrottle {
+            clock,
+            sent: HashMap::new()
+        }
+    }
+    /// Returns a `Packet::Typing` for `channel` to send,
+    /// unless one was already sent during the last `TYPING_TIMEOUT` seconds.
+    pub fn typing(&mut self, channel: usize) -> Option<Packet> {
+        let now = self.clock.now();
+        if let Some(sent) = self.sent.get(&channel) {
+            if now.duration_since(*sent) < typing_timeout() {
+                return None;
+            }
+        }
+        self.sent.insert(channel, now);
+        Some(Packet::Typing(common::Typing {
+            channel
+        }))
+    }
+    /// Allow typing in `channel` again right away,
+    /// for example after sending a message which ends the typing indicator.
+    pub fn reset(&mut self, channel: usize) {
+        self.sent.remove(&channel);
+    }
+}
@@ -0,0 +1,93 @@
+#![cfg(any(feature = "tokio", feature = "tokio-rustls"))]
+extern crate synac;
+
+mod fixtures;
+
+use fixtures::typing;
+use std::io;
+use std::time::Duration;
+use synac::common::{self, *};
+use synac::AsyncSession;
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+fn channel(packet: Packet) -> usize {
+    match packet {
+        Packet::Typing(typing) => typing.channel,
+        packet => panic!("unexpected packet {:?}", packet)
+    }
+}
+fn encode(packet: &Packet) -> Vec<u8> {
+    let mut buf = Vec::new();
+    common::write(&mut buf, packet).unwrap();
+    buf
+}
+
+#[tokio::test]
+async fn round_trip() {
+    let (client, mut server) = duplex(1024);
+    let mut session = AsyncSession::from_stream(client);
+
+    session.write(&typing(1)).await.unwrap();
+    let mut buf = vec![0; encode(&typing(1)).len()];
+    server.read_exact(&mut buf).await.unwrap();
+    assert_eq!(channel(common::read(&mut &buf[..]).unwrap()), 1);
+
+    server.write_all(&encode(&typing(2))).await.unwrap();
+    assert_eq!(channel(session.read().await.unwrap()), 2);
+
+    // the server hanging up ends the session
+    drop(server);
+    assert!(session.read().await.is_err());
+}
+#[tokio::test]
+async fn read_timeout_keeps_partial_packet() {
+    let (client, mut server) = duplex(1024);
+    let mut session = AsyncSession::from_stream(client);
+    session.set_read_timeout(Some(Duration::from_millis(10)));
+
+    let err = session.read().await.unwrap_err();
+    assert_eq!(err.downcast::<io::Error>().unwrap().kind(), io::ErrorKind::TimedOut);
+
+    // a packet split across reads, with a timeout in the middle
+    let frame = encode(&typing(3));
+    server.write_all(&frame[..1]).await.unwrap();
+    assert!(session.read().await.is_err());
+    server.write_all(&frame[1..]).await.unwrap();
+    assert_eq!(channel(session.read().await.unwrap()), 3);
+}
+#[tokio::test]
+async fn frame_split_across_reads() {
+    let (client, mut server) = duplex(1024);
+    let mut session = AsyncSession::from_stream(client);
+
+    let mut frames = encode(&typing(4));
+    frames.extend(encode(&typing(5)));
+    let writer = tokio::spawn(async move {
+        for byte in frames {
+            server.write_all(&[byte]).await.unwrap();
+            tokio::task::yield_now().await;
+        }
+        server
+    });
+    assert_eq!(channel(session.read().await.unwrap()), 4);
+    assert_eq!(channel(session.read().await.unwrap()), 5);
+    writer.await.unwrap();
+}
+#[cfg(feature = "testing")]
+#[tokio::test]
+async fn mock_server() {
+    use synac::testing::MockServer;
+    use tokio::net::TcpStream;
+
+    let (addr, server) = MockServer::new()
+        .login(5)
+        .expect(typing(1))
+        .bind()
+        .unwrap();
+    let mut session = AsyncSession::from_stream(TcpStream::connect(addr).await.unwrap());
+    assert_eq!(session.login(true, "bot", "token").await.unwrap().id, 5);
+    session.send_typing(1).await.unwrap();
+
+    drop(session);
+    server.finish().unwrap();
+}
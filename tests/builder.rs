@@ -0,0 +1,24 @@
+extern crate synac;
+
+use synac::{MissingServerName, SessionBuilder};
+
+#[test]
+fn ca_needs_server_name() {
+    // fails before connecting, so nothing has to listen there
+    let err = match SessionBuilder::new().connect("127.0.0.1:1") {
+        Ok(_) => panic!("connected without a server name"),
+        Err(err) => err
+    };
+    assert_eq!(err.downcast::<MissingServerName>().unwrap(), MissingServerName);
+}
+#[cfg(all(feature = "openssl", feature = "rustls"))]
+#[test]
+fn both_backends() {
+    use synac::{OpensslStream, RustlsStream, Session};
+
+    let builder = SessionBuilder::new();
+    let openssl: Result<Session<OpensslStream>, _> = builder.connect_openssl("127.0.0.1:1");
+    let rustls: Result<Session<RustlsStream>, _> = builder.connect_rustls("127.0.0.1:1");
+    assert_eq!(openssl.err().unwrap().downcast::<MissingServerName>().unwrap(), MissingServerName);
+    assert_eq!(rustls.err().unwrap().downcast::<MissingServerName>().unwrap(), MissingServerName);
+}
@@ -0,0 +1,63 @@
+extern crate synac;
+
+mod fixtures;
+
+use synac::common::Message;
+use synac::MessageCache;
+
+fn message(id: usize, channel: usize, text: &str) -> Message {
+    Message { text: text.as_bytes().to_vec(), ..fixtures::message(id, channel, 1) }
+}
+fn ids(cache: &MessageCache, channel: usize) -> Vec<usize> {
+    cache.messages(channel).map(|message| message.id).collect()
+}
+
+#[test]
+fn evicts_oldest_per_channel() {
+    let mut cache = MessageCache::new(3);
+    for id in &[2, 1, 4, 3] {
+        cache.insert(&message(*id, 1, "hi"));
+    }
+    cache.insert(&message(5, 2, "elsewhere"));
+    assert_eq!(ids(&cache, 1), [2, 3, 4]);
+    assert!(cache.message(1).is_none());
+    assert_eq!(ids(&cache, 2), [5]);
+    assert_eq!(cache.last_message(1).map(|message| message.id), Some(4));
+
+    // an older message than everything kept is dropped right away
+    cache.insert(&message(0, 1, "ancient"));
+    assert_eq!(ids(&cache, 1), [2, 3, 4]);
+    assert!(cache.message(0).is_none());
+}
+#[test]
+fn revisions() {
+    let mut cache = MessageCache::new(10);
+    cache.insert(&message(1, 1, "first"));
+    cache.insert(&message(1, 1, "first"));
+    assert!(cache.revisions(1).is_empty());
+
+    cache.insert(&message(1, 1, "second"));
+    cache.insert(&Message { timestamp_edit: Some(5), ..message(1, 1, "second") });
+    let revisions: Vec<_> = cache.revisions(1).iter().map(|message| message.text.clone()).collect();
+    assert_eq!(revisions, [b"first".to_vec(), b"second".to_vec()]);
+    assert_eq!(cache.message(1).unwrap().timestamp_edit, Some(5));
+    assert_eq!(ids(&cache, 1), [1]);
+}
+#[test]
+fn remove() {
+    let mut cache = MessageCache::new(10);
+    cache.insert(&message(1, 1, "a"));
+    cache.insert(&message(2, 1, "b"));
+    cache.insert(&message(3, 2, "c"));
+
+    assert_eq!(cache.message_channel(2), Some(1));
+    assert_eq!(cache.remove(2).map(|message| message.text), Some(b"b".to_vec()));
+    assert!(cache.remove(2).is_none());
+    assert!(cache.revisions(2).is_empty());
+    assert_eq!(ids(&cache, 1), [1]);
+
+    cache.remove_channel(1);
+    assert!(cache.message(1).is_none());
+    assert_eq!(ids(&cache, 1), Vec::<usize>::new());
+    assert_eq!(ids(&cache, 2), [3]);
+}
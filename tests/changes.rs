@@ -0,0 +1,52 @@
+extern crate synac;
+
+mod fixtures;
+
+use fixtures::{receive_channel, receive_user};
+use synac::common::*;
+use synac::{Change, Mode, State};
+
+fn channel(name: &str, default_mode_user: u8) -> Packet {
+    receive_channel(Channel { default_mode_user, ..fixtures::channel(1, name) })
+}
+fn user(name: &str, admin: bool, ban: bool, modes: &[(usize, u8)]) -> Packet {
+    receive_user(User {
+        admin,
+        ban,
+        modes: modes.iter().cloned().collect(),
+        name: name.to_string(),
+        ..fixtures::user(2)
+    })
+}
+
+#[test]
+fn channels() {
+    let mut state = State::new();
+    assert_eq!(state.update_with_changes(&channel("general", PERM_READ)), [Change::ChannelCreated(1)]);
+    assert!(state.update_with_changes(&channel("general", PERM_READ)).is_empty());
+    assert_eq!(state.update_with_changes(&channel("lobby", PERM_READ | PERM_WRITE)), [
+        Change::ChannelRenamed { channel: 1, old: String::from("general"), new: String::from("lobby") },
+        Change::ChannelDefaultModeChanged { channel: 1, bot: false, old: Mode::READ, new: Mode::READ | Mode::WRITE }
+    ]);
+
+    let delete = Packet::ChannelDeleteReceive(ChannelDeleteReceive { inner: Channel { id: 1, ..Default::default() } });
+    assert_eq!(state.update_with_changes(&delete), [Change::ChannelDeleted(1)]);
+    assert!(state.update_with_changes(&delete).is_empty());
+}
+#[test]
+fn users() {
+    let mut state = State::new();
+    assert_eq!(state.update_with_changes(&user("alice", false, false, &[(1, PERM_READ)])), [Change::UserJoined(2)]);
+    assert_eq!(state.update_with_changes(&user("alice", true, true, &[(3, PERM_WRITE)])), [
+        Change::UserBanned(2),
+        Change::AdminGranted(2),
+        Change::UserModeChanged { user: 2, channel: 1, old: Some(Mode::READ), new: None },
+        Change::UserModeChanged { user: 2, channel: 3, old: None, new: Some(Mode::WRITE) }
+    ]);
+    assert_eq!(state.update_with_changes(&user("bob", false, false, &[(3, PERM_WRITE)])), [
+        Change::UserRenamed { user: 2, old: String::from("alice"), new: String::from("bob") },
+        Change::UserUnbanned(2),
+        Change::AdminRevoked(2)
+    ]);
+    assert_eq!(state.users[&2].name, "bob");
+}
@@ -0,0 +1,143 @@
+extern crate synac;
+
+mod fixtures;
+
+use fixtures::{login, receive_channel, receive_user};
+use synac::common::*;
+use synac::{BotCommand, CommandError, Mode, Router, State};
+
+fn user(id: usize, admin: bool) -> Packet {
+    receive_user(User { admin, ..fixtures::user(id) })
+}
+fn private_channel(id: usize, users: &[usize]) -> Vec<Packet> {
+    let mut packets = vec![receive_channel(Channel { private: true, ..fixtures::channel(id, "") })];
+    for &user in users {
+        packets.push(receive_user(User {
+            modes: vec![(id, PERM_READ | PERM_WRITE)].into_iter().collect(),
+            ..fixtures::user(user)
+        }));
+    }
+    packets
+}
+fn command(author: usize, args: &[&str]) -> Packet {
+    Packet::CommandReceive(CommandReceive {
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        author
+    })
+}
+/// The bot is user 1, user 2 has a private channel 10 with it, user 3 has none
+fn state() -> State {
+    let mut state = State::new();
+    state.update(&login(1));
+    for packet in private_channel(10, &[1, 2]) {
+        state.update(&packet);
+    }
+    state.update(&user(3, true));
+    state
+}
+fn router() -> Router {
+    Router::new()
+        .command(BotCommand::new("roll", |ctx| {
+            let sides: u32 = ctx.arg("sides")?;
+            let count = ctx.optional_arg("count")?.unwrap_or(1u32);
+            if sides == 0 {
+                return Err(CommandError::Failed(String::from("a die needs sides")));
+            }
+            ctx.reply(format!("{}d{}", count, sides));
+            Ok(())
+        }).usage("<sides> [count]").description("Roll some dice"))
+        .command(BotCommand::new("shutdown", |_| Ok(())).require_admin())
+        .command(BotCommand::new("purge", |_| Ok(())).require_mode(10, Mode::MANAGE_MESSAGES))
+}
+fn replies(packets: Vec<Packet>) -> Vec<String> {
+    packets.into_iter()
+        .map(|packet| match packet {
+            Packet::MessageCreate(event) => {
+                assert_eq!(event.channel, 10);
+                String::from_utf8(event.text).unwrap()
+            },
+            packet => panic!("unexpected packet {:?}", packet)
+        })
+        .collect()
+}
+
+#[test]
+fn typed_arguments() {
+    let state = state();
+    let mut router = router();
+    assert_eq!(replies(router.feed(&state, &command(2, &["roll", "6", "2"]))), ["2d6"]);
+    assert_eq!(replies(router.feed(&state, &command(2, &["roll", "20"]))), ["1d20"]);
+    assert_eq!(replies(router.feed(&state, &command(2, &["roll", "0"]))), ["a die needs sides"]);
+    assert_eq!(
+        replies(router.feed(&state, &command(2, &["roll", "many"]))),
+        ["invalid sides: many\nusage: roll <sides> [count]"]
+    );
+    assert_eq!(
+        replies(router.feed(&state, &command(2, &["roll"]))),
+        ["missing sides\nusage: roll <sides> [count]"]
+    );
+    assert_eq!(replies(router.feed(&state, &command(2, &["dance"]))), ["unknown command dance, try help"]);
+}
+#[test]
+fn permissions_and_help() {
+    let state = state();
+    let router = router();
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(router.dispatch(&state, 2, &args(&["shutdown"])).replies, [CommandError::Denied.to_string()]);
+    assert_eq!(router.dispatch(&state, 3, &args(&["purge"])).replies, [CommandError::Denied.to_string()]);
+    assert!(router.dispatch(&state, 3, &args(&["shutdown"])).replies.is_empty());
+
+    let help = router.dispatch(&state, 2, &args(&["help"])).replies.join("\n");
+    assert!(help.contains("roll <sides> [count] - Roll some dice"));
+    assert!(!help.contains("shutdown"));
+    assert!(router.dispatch(&state, 3, &args(&["help"])).replies[0].contains("shutdown"));
+    assert_eq!(router.dispatch(&state, 2, &args(&["help", "roll"])).replies, ["roll <sides> [count] - Roll some dice"]);
+}
+#[test]
+fn replies_wait_for_private_channel() {
+    let mut state = state();
+    let mut router = router();
+
+    let packets = router.feed(&state, &command(3, &["roll", "4"]));
+    match &*packets {
+        [Packet::ChannelCreate(event)] => assert_eq!(event.recipient, Some(3)),
+        packets => panic!("unexpected packets {:?}", packets)
+    }
+    // only one channel is requested
+    assert!(router.feed(&state, &command(3, &["roll", "8"])).is_empty());
+
+    let mut sent = Vec::new();
+    for packet in private_channel(11, &[1, 3]) {
+        state.update(&packet);
+        sent.extend(router.feed(&state, &packet));
+    }
+    let sent: Vec<_> = sent.into_iter()
+        .map(|packet| match packet {
+            Packet::MessageCreate(event) => (event.channel, String::from_utf8(event.text).unwrap()),
+            packet => panic!("unexpected packet {:?}", packet)
+        })
+        .collect();
+    assert_eq!(sent, [(11, String::from("1d4")), (11, String::from("1d8"))]);
+    assert_eq!(router.feed(&state, &user(3, true)).len(), 0);
+}
+#[test]
+fn failed_private_channel_drops_replies() {
+    let state = state();
+    let mut router = router();
+    let request = |router: &mut Router| {
+        let packets = router.feed(&state, &command(3, &["roll", "4"]));
+        assert!(matches!(&*packets, [Packet::ChannelCreate(event)] if event.recipient == Some(3)));
+        packets
+    };
+
+    // the server refuses
+    request(&mut router);
+    assert!(router.feed(&state, &Packet::Err(ERR_MISSING_PERMISSION)).is_empty());
+    // so the next reply requests a channel again
+    let packets = request(&mut router);
+
+    // sending fails
+    router.send_failed(&packets[0]);
+    request(&mut router);
+}
@@ -0,0 +1,73 @@
+extern crate synac;
+
+mod fixtures;
+
+use fixtures::{login, receive_channel, receive_user};
+use synac::common::*;
+use synac::State;
+
+fn channel(id: usize, private: bool) -> Packet {
+    receive_channel(Channel {
+        default_mode_user: if private { 0 } else { PERM_READ | PERM_WRITE },
+        private,
+        ..fixtures::channel(id, "")
+    })
+}
+fn user(id: usize, channels: &[usize]) -> Packet {
+    receive_user(User {
+        modes: channels.iter().map(|&channel| (channel, PERM_READ | PERM_WRITE)).collect(),
+        ..fixtures::user(id)
+    })
+}
+
+/// Users 1 and 2 share private channel 10, user 1 also has private channel 11 with user 3
+fn state_as(me: usize) -> State {
+    let mut state = State::new();
+    state.update(&login(me));
+    state.update(&channel(1, false));
+    state.update(&channel(10, true));
+    if me != 2 {
+        state.update(&channel(11, true));
+    }
+    state.update(&user(1, &[10, 11]));
+    state.update(&user(2, &[10]));
+    state.update(&user(3, &[11]));
+    state
+}
+
+#[test]
+fn dm_from_both_sides() {
+    let state = state_as(1);
+    assert_eq!(state.dm_channel(2).map(|channel| channel.id), Some(10));
+    assert_eq!(state.dm_partner(10).map(|user| user.id), Some(2));
+
+    let state = state_as(2);
+    assert_eq!(state.dm_channel(1).map(|channel| channel.id), Some(10));
+    assert_eq!(state.dm_partner(10).map(|user| user.id), Some(1));
+}
+
+#[test]
+fn dm_with_nobody() {
+    let state = state_as(1);
+    assert!(state.dm_channel(1).is_none());
+    assert!(state.dm_partner(1).is_none());
+
+    let state = state_as(2);
+    assert!(state.dm_channel(3).is_none());
+}
+
+#[test]
+fn list_dms() {
+    let state = state_as(1);
+    let dms: Vec<_> = state.list_dms().into_iter()
+        .map(|(channel, user)| (channel.id, user.map(|user| user.id)))
+        .collect();
+    assert_eq!(dms, vec![(10, Some(2)), (11, Some(3))]);
+}
+
+#[test]
+fn recipient_requires_private() {
+    let state = state_as(1);
+    assert!(state.get_recipient(&state.channels[&1], 1).is_none());
+    assert_eq!(state.get_recipient(&state.channels[&10], 1).map(|user| user.id), Some(2));
+}
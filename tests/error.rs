@@ -0,0 +1,43 @@
+extern crate synac;
+
+use synac::common::*;
+use synac::ServerError;
+
+#[test]
+fn code_round_trip() {
+    let codes = [
+        ERR_ALREADY_EXISTS,
+        ERR_LIMIT_REACHED,
+        ERR_LOGIN_BANNED,
+        ERR_LOGIN_BOT,
+        ERR_LOGIN_INVALID,
+        ERR_MAX_CONN_PER_IP,
+        ERR_MISSING_FIELD,
+        ERR_MISSING_PERMISSION,
+        ERR_SELF_PM,
+        ERR_UNKNOWN_BOT,
+        ERR_UNKNOWN_CHANNEL,
+        ERR_UNKNOWN_MESSAGE,
+        ERR_UNKNOWN_USER
+    ];
+    for &code in &codes {
+        let err = ServerError::from(code);
+        assert_ne!(err, ServerError::Unknown(code), "code {} isn't known", code);
+        assert_eq!(u8::from(err), code);
+        assert_eq!(err.code(), code);
+    }
+
+    // codes added to the protocol later are kept
+    assert_eq!(ServerError::from(200), ServerError::Unknown(200));
+    assert_eq!(u8::from(ServerError::Unknown(200)), 200);
+}
+#[test]
+fn display() {
+    assert_eq!(ServerError::MissingPermission.to_string(), "missing permission");
+    assert_eq!(ServerError::Unknown(200).to_string(), "unknown error code 200");
+}
+#[test]
+fn check() {
+    assert_eq!(ServerError::check(Packet::Err(ERR_UNKNOWN_USER)).unwrap_err(), ServerError::UnknownUser);
+    assert!(ServerError::check(Packet::MessageListReceived).is_ok());
+}
@@ -0,0 +1,52 @@
+extern crate synac;
+
+use synac::{Fingerprint, InvalidFingerprint};
+
+/// `openssl x509 -in tests/data/cert.pem -pubkey -noout | openssl pkey -pubin -outform pem | sha256sum`
+const HEX: &str = "A1617A00C22AE3A546570B1E84C66C2CE721F488B7AD2EE71B96FD82908FCAEC";
+const COLON_HEX: &str = "A1:61:7A:00:C2:2A:E3:A5:46:57:0B:1E:84:C6:6C:2C:E7:21:F4:88:B7:AD:2E:E7:1B:96:FD:82:90:8F:CA:EC";
+const BASE64: &str = "oWF6AMIq46VGVwsehMZsLOch9Ii3rS7nG5b9gpCPyuw=";
+
+fn expected() -> Fingerprint {
+    HEX.parse().unwrap()
+}
+
+#[test]
+fn formats() {
+    let fingerprint = expected();
+    assert_eq!(fingerprint.to_hex(), HEX);
+    assert_eq!(fingerprint.to_string(), HEX);
+    assert_eq!(fingerprint.to_colon_hex(), COLON_HEX);
+    assert_eq!(fingerprint.to_base64(), BASE64);
+
+    assert_eq!(COLON_HEX.parse(), Ok(fingerprint));
+    assert_eq!(BASE64.parse(), Ok(fingerprint));
+    assert_eq!(HEX.to_lowercase().parse(), Ok(fingerprint));
+    assert_eq!(COLON_HEX.to_lowercase().parse(), Ok(fingerprint));
+    assert_eq!(format!("  {}\n", HEX).parse(), Ok(fingerprint));
+    assert!(fingerprint.matches(&COLON_HEX.to_lowercase()));
+    assert!(!fingerprint.matches("A1617A00"));
+}
+#[test]
+fn invalid() {
+    assert_eq!("".parse::<Fingerprint>(), Err(InvalidFingerprint));
+    assert_eq!(HEX[2..].parse::<Fingerprint>(), Err(InvalidFingerprint));
+    assert_eq!(HEX.replace('A', "G").parse::<Fingerprint>(), Err(InvalidFingerprint));
+    // valid base64, but not 32 bytes
+    assert_eq!("aGVsbG8=".parse::<Fingerprint>(), Err(InvalidFingerprint));
+}
+#[test]
+fn matches_openssl() {
+    // same hash for the key and the certificate, in both encodings, with either backend
+    assert_eq!(Fingerprint::from_public_key_pem(include_bytes!("data/key.pem")).unwrap(), expected());
+    assert_eq!(Fingerprint::from_public_key_der(include_bytes!("data/key.der")), expected());
+    assert_eq!(Fingerprint::from_cert_pem(include_bytes!("data/cert.pem")).unwrap(), expected());
+    assert_eq!(Fingerprint::from_cert_der(include_bytes!("data/cert.der")).unwrap(), expected());
+
+    // line endings don't matter
+    let crlf = String::from_utf8_lossy(include_bytes!("data/cert.pem")).replace('\n', "\r\n");
+    assert_eq!(Fingerprint::from_cert_pem(crlf.as_bytes()).unwrap(), expected());
+
+    assert!(Fingerprint::from_cert_pem(include_bytes!("data/key.pem")).is_err());
+    assert!(Fingerprint::from_cert_der(b"not a certificate").is_err());
+}
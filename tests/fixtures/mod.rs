@@ -0,0 +1,60 @@
+//! Fixtures shared by the integration tests. Each test only uses some of them.
+#![allow(dead_code)]
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use synac::common::*;
+
+/// A user named after its id
+pub fn user(id: usize) -> User {
+    User {
+        id,
+        name: format!("user{}", id),
+        ..Default::default()
+    }
+}
+/// A public channel
+pub fn channel(id: usize, name: &str) -> Channel {
+    Channel {
+        id,
+        name: name.to_string(),
+        ..Default::default()
+    }
+}
+/// A message without any text
+pub fn message(id: usize, channel: usize, author: usize) -> Message {
+    Message {
+        author,
+        channel,
+        id,
+        ..Default::default()
+    }
+}
+
+pub fn receive_user(user: User) -> Packet {
+    Packet::UserReceive(UserReceive { inner: user })
+}
+pub fn receive_channel(channel: Channel) -> Packet {
+    Packet::ChannelReceive(ChannelReceive { inner: channel })
+}
+pub fn receive_message(message: Message, new: bool) -> Packet {
+    Packet::MessageReceive(MessageReceive { inner: message, new })
+}
+/// Logged in as user `id`
+pub fn login(id: usize) -> Packet {
+    Packet::LoginSuccess(LoginSuccess { created: false, id, token: String::new() })
+}
+pub fn typing(channel: usize) -> Packet {
+    Packet::Typing(Typing { channel })
+}
+
+/// A clock that only moves when `advance` is called
+pub fn clock() -> (Rc<Cell<Instant>>, impl Fn() -> Instant) {
+    let now = Rc::new(Cell::new(Instant::now()));
+    let clock = Rc::clone(&now);
+    (now, move || clock.get())
+}
+pub fn advance(now: &Cell<Instant>, duration: Duration) {
+    now.set(now.get() + duration);
+}
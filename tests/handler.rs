@@ -0,0 +1,106 @@
+extern crate synac;
+
+mod fixtures;
+
+use fixtures::{receive_channel, receive_message, receive_user};
+use synac::common::*;
+use synac::{Driver, EventContext, EventHandler, State};
+
+#[derive(Default)]
+struct Recorder {
+    events: Vec<String>
+}
+
+impl EventHandler for Recorder {
+    fn on_message(&mut self, ctx: &mut EventContext, message: &Message) {
+        let author = ctx.author.map(|user| user.name.clone()).unwrap_or_default();
+        let channel = ctx.channel.map(|channel| channel.name.clone()).unwrap_or_default();
+        self.events.push(format!("message {} from {} in {}", message.id, author, channel));
+        ctx.reply("pong");
+    }
+    fn on_message_edit(&mut self, _: &mut EventContext, message: &Message) {
+        self.events.push(format!("edit {}", message.id));
+    }
+    fn on_message_history(&mut self, _: &mut EventContext, message: &Message) {
+        self.events.push(format!("history {}", message.id));
+    }
+    fn on_message_delete(&mut self, ctx: &mut EventContext, id: usize, message: Option<&Message>) {
+        let author = ctx.author.map(|user| user.id);
+        self.events.push(format!("delete {} {:?} {:?}", id, message.map(|message| message.id), author));
+    }
+    fn on_channel_create(&mut self, _: &mut EventContext, channel: &Channel) {
+        self.events.push(format!("create {}", channel.name));
+    }
+    fn on_channel_update(&mut self, _: &mut EventContext, channel: &Channel) {
+        self.events.push(format!("update {}", channel.name));
+    }
+    fn on_typing(&mut self, ctx: &mut EventContext) {
+        self.events.push(format!("typing {:?}", ctx.author.map(|user| user.id)));
+    }
+    fn on_command(&mut self, ctx: &mut EventContext, args: &[String]) {
+        self.events.push(format!("command {} from {:?}", args.join(" "), ctx.author.map(|user| user.id)));
+        assert!(!ctx.reply("nowhere to reply"));
+    }
+    fn on_error(&mut self, _: &mut EventContext, error: synac::ServerError) {
+        self.events.push(format!("error {:?}", error));
+    }
+}
+
+fn channel(name: &str) -> Packet {
+    receive_channel(fixtures::channel(1, name))
+}
+fn message(id: usize, new: bool, edited: Option<i64>) -> Packet {
+    receive_message(Message { timestamp_edit: edited, ..fixtures::message(id, 1, 2) }, new)
+}
+
+#[test]
+fn callbacks() {
+    let mut driver = Driver::with_state(State::with_message_cache(10), Recorder::default());
+    driver.handle(&receive_user(User { name: String::from("alice"), ..fixtures::user(2) }));
+    driver.handle(&channel("general"));
+    driver.handle(&channel("renamed"));
+
+    let sent = driver.handle(&message(5, true, None));
+    match &*sent {
+        [Packet::MessageCreate(event)] => assert_eq!((event.channel, &*event.text), (1, &b"pong"[..])),
+        packets => panic!("unexpected packets {:?}", packets)
+    }
+    driver.handle(&message(5, false, Some(1)));
+    driver.handle(&message(5, false, Some(1)));
+    driver.handle(&message(3, false, None));
+    // edits of messages that aren't cached are still edits
+    driver.handle(&message(2, false, Some(1)));
+    driver.handle(&Packet::MessageDeleteReceive(MessageDeleteReceive { id: 5 }));
+    driver.handle(&Packet::MessageDeleteReceive(MessageDeleteReceive { id: 9 }));
+    driver.handle(&Packet::TypingReceive(TypingReceive { author: 2, channel: 1 }));
+    assert!(driver.handle(&Packet::CommandReceive(CommandReceive { args: vec![String::from("ping")], author: 2 })).is_empty());
+    driver.handle(&Packet::Err(ERR_UNKNOWN_BOT));
+
+    assert_eq!(driver.handler.events, [
+        "create general",
+        "update renamed",
+        "message 5 from alice in renamed",
+        "edit 5",
+        "history 5",
+        "history 3",
+        "edit 2",
+        "delete 5 Some(5) Some(2)",
+        "delete 9 None None",
+        "typing Some(2)",
+        "command ping from Some(2)",
+        "error UnknownBot"
+    ]);
+    assert_eq!(driver.state.channels[&1].name, "renamed");
+}
+#[test]
+fn edits_without_cache() {
+    let mut driver = Driver::new(Recorder::default());
+    driver.handle(&message(5, true, None));
+    driver.handle(&message(5, false, Some(1)));
+    driver.handle(&message(4, false, None));
+    assert_eq!(driver.handler.events, [
+        "message 5 from  in ",
+        "edit 5",
+        "history 4"
+    ]);
+}
@@ -0,0 +1,148 @@
+extern crate synac;
+
+mod fixtures;
+
+use fixtures::receive_message;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+use synac::common::*;
+use synac::{Direction, Feed, HistoryPager, ServerError, Session};
+
+fn message(id: usize, channel: usize) -> Packet {
+    receive_message(fixtures::message(id, channel, 1), false)
+}
+fn request(pager: &mut HistoryPager) -> Option<(Option<usize>, Option<usize>, usize)> {
+    match pager.request()? {
+        Packet::MessageList(list) => Some((list.before, list.after, list.limit)),
+        packet => panic!("unexpected packet {:?}", packet)
+    }
+}
+/// Feed a page of `ids` and return what the pager made of it
+fn page(pager: &mut HistoryPager, ids: &[usize]) -> Vec<usize> {
+    for &id in ids {
+        match pager.feed(&message(id, 1)) {
+            Feed::Consumed => (),
+            feed => panic!("unexpected feed {:?}", feed)
+        }
+    }
+    match pager.feed(&Packet::MessageListReceived) {
+        Feed::Page(page) => page.into_iter().map(|message| message.id).collect(),
+        feed => panic!("unexpected feed {:?}", feed)
+    }
+}
+
+#[test]
+fn backwards() {
+    let mut pager = HistoryPager::new(1, Direction::Backwards, 2);
+    assert_eq!(request(&mut pager), Some((None, None, 2)));
+    assert_eq!(page(&mut pager, &[8, 9]), [9, 8]);
+    assert_eq!(request(&mut pager), Some((Some(8), None, 2)));
+
+    // messages outside the cursor or in other channels aren't part of the page
+    match pager.feed(&message(9, 1)) {
+        Feed::Ignored => (),
+        feed => panic!("unexpected feed {:?}", feed)
+    }
+    match pager.feed(&message(5, 2)) {
+        Feed::Ignored => (),
+        feed => panic!("unexpected feed {:?}", feed)
+    }
+    assert_eq!(page(&mut pager, &[6, 7, 6]), [7, 6]);
+    assert_eq!(request(&mut pager), Some((Some(6), None, 2)));
+
+    // a page that isn't full is the last one
+    assert_eq!(page(&mut pager, &[3]), [3]);
+    assert!(pager.is_done());
+    assert_eq!(request(&mut pager), None);
+}
+#[test]
+fn forwards() {
+    let mut pager = HistoryPager::new(1, Direction::Forwards, 2).starting_at(4);
+    assert_eq!(request(&mut pager), Some((None, Some(4), 2)));
+    assert_eq!(page(&mut pager, &[6, 5]), [5, 6]);
+    assert_eq!(request(&mut pager), Some((None, Some(6), 2)));
+    assert_eq!(page(&mut pager, &[7, 8]), [7, 8]);
+    assert_eq!(request(&mut pager), Some((None, Some(8), 2)));
+    assert_eq!(page(&mut pager, &[]), Vec::<usize>::new());
+    assert!(pager.is_done());
+
+    // the oldest message can only be reached through after
+    let mut pager = HistoryPager::new(1, Direction::Forwards, 2);
+    assert_eq!(request(&mut pager), Some((None, Some(0), 2)));
+}
+#[test]
+fn empty_channel() {
+    let mut pager = HistoryPager::new(1, Direction::Backwards, 10);
+    assert_eq!(request(&mut pager), Some((None, None, 10)));
+    assert_eq!(page(&mut pager, &[]), Vec::<usize>::new());
+    assert!(pager.is_done());
+    assert_eq!(request(&mut pager), None);
+}
+#[test]
+fn page_size_is_clamped() {
+    assert_eq!(request(&mut HistoryPager::new(1, Direction::Backwards, LIMIT_BULK + 1)), Some((None, None, LIMIT_BULK)));
+    assert_eq!(request(&mut HistoryPager::new(1, Direction::Backwards, 0)), Some((None, None, 1)));
+}
+#[test]
+fn rate_limits_and_errors() {
+    let mut pager = HistoryPager::new(1, Direction::Backwards, 2);
+    request(&mut pager);
+    pager.feed(&message(5, 1));
+    match pager.feed(&Packet::RateLimited(3)) {
+        Feed::RateLimited(delay) => assert_eq!(delay, Duration::from_secs(3)),
+        feed => panic!("unexpected feed {:?}", feed)
+    }
+    // the partial page was dropped, so the same request is sent again
+    assert_eq!(request(&mut pager), Some((None, None, 2)));
+    assert_eq!(page(&mut pager, &[9, 8]), [9, 8]);
+
+    request(&mut pager);
+    match pager.feed(&Packet::Err(ERR_MISSING_PERMISSION)) {
+        Feed::Failed(err) => assert_eq!(err, ServerError::MissingPermission),
+        feed => panic!("unexpected feed {:?}", feed)
+    }
+    assert_eq!(request(&mut pager), None);
+}
+
+/// Accepts writes, but every read fails
+struct Broken;
+
+impl Read for Broken {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::from(io::ErrorKind::ConnectionReset))
+    }
+}
+impl Write for Broken {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn iterator_ends_after_error() {
+    let mut session = Session::from_stream(Broken);
+    let mut history = session.history(1, Direction::Backwards, 10);
+    assert!(history.next().unwrap().is_err());
+    assert!(history.next().is_none());
+    assert!(history.next().is_none());
+}
+#[test]
+fn ignored_without_request() {
+    let mut pager = HistoryPager::new(1, Direction::Backwards, 2);
+    request(&mut pager);
+    assert_eq!(page(&mut pager, &[8, 9]), [9, 8]);
+
+    // edits, errors and other list responses while no page is requested aren't the pager's
+    for packet in &[message(7, 1), Packet::Err(ERR_MISSING_PERMISSION), Packet::RateLimited(3), Packet::MessageListReceived] {
+        match pager.feed(packet) {
+            Feed::Ignored => (),
+            feed => panic!("unexpected feed {:?}", feed)
+        }
+    }
+    assert!(!pager.is_done());
+    assert_eq!(request(&mut pager), Some((Some(8), None, 2)));
+    assert_eq!(page(&mut pager, &[6]), [6]);
+}
@@ -0,0 +1,90 @@
+extern crate synac;
+
+mod fixtures;
+
+use fixtures::{advance, clock, login, receive_message, receive_user};
+use std::time::Duration;
+use synac::common::*;
+use synac::{InvocationFeed, Invocations, ServerError, State};
+
+fn user(id: usize, bot: bool) -> Packet {
+    receive_user(User { bot, ..fixtures::user(id) })
+}
+fn message(id: usize, author: usize, new: bool) -> Packet {
+    receive_message(Message { text: b"pong".to_vec(), ..fixtures::message(id, 10, author) }, new)
+}
+/// You are user 1, users 2 and 3 are bots and user 4 is not
+fn state() -> State {
+    let mut state = State::new();
+    state.update(&login(1));
+    state.update(&user(2, true));
+    state.update(&user(3, true));
+    state.update(&user(4, false));
+    state
+}
+
+#[test]
+fn bots() {
+    let ids: Vec<_> = state().bots().iter().map(|user| user.id).collect();
+    assert_eq!(ids, [2, 3]);
+}
+#[test]
+fn replies() {
+    let state = state();
+    let mut invocations = Invocations::new();
+    let (first, packet) = invocations.invoke(2, vec![String::from("ping")]);
+    match packet {
+        Packet::Command(command) => assert_eq!((command.recipient, &*command.args), (2, &[String::from("ping")][..])),
+        packet => panic!("unexpected packet {:?}", packet)
+    }
+    let (second, _) = invocations.invoke(2, vec![String::from("ping")]);
+
+    match invocations.feed(&state, &message(1, 2, true)) {
+        InvocationFeed::Reply { id, message } => assert_eq!((id, message.id), (second, 1)),
+        feed => panic!("unexpected feed {:?}", feed)
+    }
+    assert!(invocations.get(second).unwrap().replied);
+    assert!(!invocations.get(first).unwrap().replied);
+
+    // history, your own messages and other users are not replies
+    for packet in &[message(2, 2, false), message(3, 1, true), message(4, 3, true)] {
+        match invocations.feed(&state, packet) {
+            InvocationFeed::Ignored => (),
+            feed => panic!("unexpected feed {:?}", feed)
+        }
+    }
+
+    assert_eq!(invocations.finish(first).map(|invocation| invocation.id), Some(first));
+    assert_eq!(invocations.active().len(), 1);
+}
+#[test]
+fn unknown_bot() {
+    let state = state();
+    let mut invocations = Invocations::new();
+    invocations.invoke(2, vec![String::from("ping")]);
+    let (failing, _) = invocations.invoke(4, vec![String::from("ping")]);
+
+    match invocations.feed(&state, &Packet::Err(ERR_UNKNOWN_BOT)) {
+        InvocationFeed::Failed { id, error } => assert_eq!((id, error), (failing, ServerError::UnknownBot)),
+        feed => panic!("unexpected feed {:?}", feed)
+    }
+    assert!(invocations.get(failing).is_none());
+    assert_eq!(invocations.active().len(), 1);
+}
+#[test]
+fn expire() {
+    let (now, clock) = clock();
+    let mut invocations = Invocations::with_clock(clock);
+    let (old, _) = invocations.invoke(2, vec![String::from("ping")]);
+    advance(&now, Duration::from_secs(5));
+    let (new, _) = invocations.invoke(3, vec![String::from("ping")]);
+    assert_eq!(invocations.get(new).unwrap().sent, now.get());
+
+    advance(&now, Duration::from_secs(5));
+    assert!(invocations.expire(Duration::from_secs(10)).is_empty());
+    advance(&now, Duration::from_millis(1));
+    let expired: Vec<_> = invocations.expire(Duration::from_secs(10)).into_iter().map(|invocation| invocation.id).collect();
+    assert_eq!(expired, [old]);
+    assert_eq!(invocations.active().len(), 1);
+    assert!(invocations.get(new).is_some());
+}
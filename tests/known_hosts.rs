@@ -0,0 +1,83 @@
+extern crate synac;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use synac::{HostKeyChanged, HostStatus, KnownHosts};
+
+const KEY: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+const OTHER: &str = "fedcba9876543210fedcba9876543210fedcba9876543210fedcba9876543210";
+
+fn temp_file(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("synac-known-hosts-{}-{}", std::process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn first_seen_host_is_trusted() {
+    let mut hosts = KnownHosts::new();
+    assert_eq!(hosts.check("example.com:8439", KEY), HostStatus::Unknown);
+    assert_eq!(hosts.trust("example.com:8439", KEY), Ok(true));
+    assert_eq!(hosts.hashes("example.com:8439"), [KEY]);
+    // other ports are other hosts
+    assert_eq!(hosts.check("example.com:8440", KEY), HostStatus::Unknown);
+}
+#[test]
+fn matching_key_is_accepted() {
+    let mut hosts = KnownHosts::parse(&format!("example.com:8439 {}\n", KEY));
+    assert_eq!(hosts.trust("example.com:8439", KEY), Ok(false));
+    // in any format and case
+    let colons = KEY.as_bytes().chunks(2)
+        .map(|pair| String::from_utf8_lossy(pair).to_uppercase())
+        .collect::<Vec<_>>()
+        .join(":");
+    assert_eq!(hosts.trust("example.com:8439", &colons), Ok(false));
+    assert_eq!(hosts.hashes("example.com:8439").len(), 1);
+}
+#[test]
+fn changed_key_is_rejected() {
+    let mut hosts = KnownHosts::parse(&format!("example.com:8439 {}\n", KEY));
+    assert_eq!(hosts.check("example.com:8439", OTHER), HostStatus::Changed);
+    assert_eq!(hosts.trust("example.com:8439", OTHER), Err(HostKeyChanged {
+        host: String::from("example.com:8439"),
+        expected: vec![String::from(KEY)],
+        actual: String::from(OTHER)
+    }));
+    // and not remembered
+    assert_eq!(hosts.hashes("example.com:8439"), [KEY]);
+
+    // until it's added next to the old one, to rotate keys
+    hosts.add("example.com:8439", OTHER);
+    assert_eq!(hosts.trust("example.com:8439", OTHER), Ok(false));
+    hosts.remove("example.com:8439", KEY);
+    assert_eq!(hosts.check("example.com:8439", KEY), HostStatus::Changed);
+}
+#[test]
+fn file_round_trips() {
+    let path = temp_file("round-trip");
+    let mut hosts = KnownHosts::open(&path).unwrap();
+    assert_eq!(hosts.path(), Some(&*path));
+    assert_eq!(hosts.trust("example.com:8439", KEY), Ok(true));
+    hosts.add("[::1]:8439", OTHER);
+    hosts.save().unwrap();
+
+    let loaded = KnownHosts::open(&path).unwrap();
+    assert_eq!(loaded.hashes("example.com:8439"), [KEY]);
+    assert_eq!(loaded.hashes("[::1]:8439"), [OTHER]);
+    assert_eq!(loaded.to_string(), hosts.to_string());
+    fs::remove_file(&path).unwrap();
+
+    assert!(KnownHosts::new().save().is_err());
+}
+#[test]
+fn malformed_lines() {
+    let contents = format!("# comment\n\nhost-without-hash\n  example.com:8439   {}  trailing\n", KEY);
+    let mut hosts = KnownHosts::parse(&contents);
+    assert!(hosts.hashes("host-without-hash").is_empty());
+    assert_eq!(hosts.check("example.com:8439", KEY), HostStatus::Known);
+
+    // lines that aren't entries are kept as they were
+    hosts.remove_host("example.com:8439");
+    assert_eq!(hosts.to_string(), "# comment\n\nhost-without-hash\n");
+}
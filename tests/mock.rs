@@ -0,0 +1,61 @@
+#![cfg(feature = "testing")]
+extern crate synac;
+
+mod fixtures;
+
+use fixtures::typing;
+use synac::common::*;
+use synac::testing::{MockError, MockServer};
+
+#[test]
+fn follows_script() {
+    let (mut session, server) = MockServer::new()
+        .login(5)
+        .expect(typing(1))
+        .reply(Packet::MessageDeleteReceive(MessageDeleteReceive { id: 2 }))
+        .spawn();
+
+    assert_eq!(session.login(true, "bot", "token").unwrap().id, 5);
+    match session.read().unwrap() {
+        Packet::LoginSuccess(_) => (),
+        packet => panic!("unexpected packet {:?}", packet)
+    }
+    session.send_typing(1).unwrap();
+    match session.read().unwrap() {
+        Packet::MessageDeleteReceive(event) => assert_eq!(event.id, 2),
+        packet => panic!("unexpected packet {:?}", packet)
+    }
+
+    drop(session);
+    server.finish().unwrap();
+}
+#[test]
+fn unexpected_packet() {
+    let (mut session, server) = MockServer::new()
+        .expect(typing(1))
+        .spawn();
+
+    session.send_typing(2).unwrap();
+    drop(session);
+    match server.finish().unwrap_err().downcast::<MockError>() {
+        Ok(MockError::Unexpected { got: Packet::Typing(event), .. }) => assert_eq!(event.channel, 2),
+        result => panic!("unexpected result {:?}", result)
+    }
+}
+#[test]
+fn extra_and_missing_packets() {
+    let (mut session, server) = MockServer::new().spawn();
+    session.send_typing(1).unwrap();
+    drop(session);
+    match server.finish().unwrap_err().downcast::<MockError>() {
+        Ok(MockError::Extra(Packet::Typing(_))) => (),
+        result => panic!("unexpected result {:?}", result)
+    }
+
+    let (session, server) = MockServer::new().expect(typing(1)).spawn();
+    drop(session);
+    match server.finish().unwrap_err().downcast::<MockError>() {
+        Ok(MockError::Disconnected { .. }) => (),
+        result => panic!("unexpected result {:?}", result)
+    }
+}
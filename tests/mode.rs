@@ -0,0 +1,101 @@
+extern crate rmp_serde as rmps;
+extern crate synac;
+
+mod fixtures;
+
+use fixtures::{receive_channel, receive_user};
+use synac::common::*;
+use synac::{get_effective_mode, Mode, ParseModeError, State};
+
+#[test]
+fn display() {
+    assert_eq!(Mode::empty().to_string(), "-----");
+    assert_eq!(Mode::ALL.to_string(), "rwcdm");
+    assert_eq!((Mode::READ | Mode::WRITE | Mode::MANAGE_MODES).to_string(), "rw--m");
+}
+#[test]
+fn parse_round_trip() {
+    for bits in 0..=PERM_ALL {
+        let mode = Mode::from_bits(bits);
+        assert_eq!(mode.to_string().parse::<Mode>(), Ok(mode));
+    }
+    assert_eq!("read, write".parse(), Ok(Mode::READ | Mode::WRITE));
+    assert_eq!("ALL -manage_modes".parse(), Ok(Mode::ALL - Mode::MANAGE_MODES));
+    assert_eq!("".parse(), Ok(Mode::empty()));
+}
+#[test]
+fn apply() {
+    let mode = Mode::READ | Mode::WRITE;
+    assert_eq!(mode.apply("+manage_messages -write"), Ok(Mode::READ | Mode::MANAGE_MESSAGES));
+    assert_eq!(mode.apply("none +read"), Ok(Mode::READ));
+    // the letter form replaces the whole mode
+    assert_eq!(mode.apply("+manage_modes --c--"), Ok(Mode::MANAGE_CHANNELS));
+}
+#[test]
+fn invalid() {
+    assert_eq!("+fly".parse::<Mode>(), Err(ParseModeError(String::from("+fly"))));
+    assert_eq!("read, rw---x".parse::<Mode>(), Err(ParseModeError(String::from("rw---x"))));
+    assert_eq!("wr---".parse::<Mode>(), Err(ParseModeError(String::from("wr---"))));
+    assert_eq!("rw--".parse::<Mode>(), Err(ParseModeError(String::from("rw--"))));
+    assert_eq!(Mode::READ.apply("+"), Err(ParseModeError(String::from("+"))));
+}
+#[test]
+fn serializes_as_u8() {
+    let mode = Mode::READ | Mode::MANAGE_CHANNELS;
+    let bytes = rmps::to_vec(&mode).unwrap();
+    assert_eq!(bytes, rmps::to_vec(&(PERM_READ | PERM_MANAGE_CHANNELS)).unwrap());
+    assert_eq!(rmps::from_slice::<Mode>(&bytes).unwrap(), mode);
+
+    // unknown bits are kept, so nothing is lost on the way through
+    let unknown = rmps::from_slice::<Mode>(&rmps::to_vec(&0xFFu8).unwrap()).unwrap();
+    assert_eq!(unknown.bits(), 0xFF);
+    assert_eq!(Mode::from_bits_truncate(0xFF), Mode::ALL);
+}
+
+fn channel(private: bool) -> Channel {
+    Channel {
+        default_mode_bot: PERM_READ,
+        default_mode_user: PERM_READ | PERM_WRITE,
+        private,
+        ..fixtures::channel(1, "channel")
+    }
+}
+#[test]
+fn effective_mode() {
+    let public = channel(false);
+    let private = channel(true);
+
+    // defaults depend on whether it's a bot
+    let user = fixtures::user(1);
+    let bot = User { bot: true, ..fixtures::user(2) };
+    assert_eq!(get_effective_mode(&public, &user), Mode::READ | Mode::WRITE);
+    assert_eq!(get_effective_mode(&public, &bot), Mode::READ);
+
+    // admins can do everything, except in private channels they're not part of
+    let mut admin = User { admin: true, ..fixtures::user(3) };
+    assert_eq!(get_effective_mode(&public, &admin), Mode::ALL);
+    assert_eq!(get_effective_mode(&private, &admin), Mode::empty());
+    admin.modes.insert(1, PERM_READ);
+    assert_eq!(get_effective_mode(&private, &admin), Mode::READ);
+
+    // banned users can't do anything, not even admins
+    let banned = User { ban: true, ..admin };
+    assert_eq!(get_effective_mode(&public, &banned), Mode::empty());
+    assert_eq!(get_effective_mode(&private, &banned), Mode::empty());
+}
+#[test]
+fn state_can() {
+    let mut state = State::new();
+    state.update(&receive_channel(channel(false)));
+    state.update(&receive_user(fixtures::user(1)));
+
+    assert_eq!(state.effective_mode(1, 1), Some(Mode::READ | Mode::WRITE));
+    assert!(state.can(1, 1, Mode::READ | Mode::WRITE));
+    assert!(!state.can(1, 1, Mode::MANAGE_MESSAGES));
+
+    // nothing is known about other users and channels
+    assert_eq!(state.effective_mode(2, 1), None);
+    assert_eq!(state.effective_mode(1, 2), None);
+    assert!(!state.can(2, 1, Mode::READ));
+    assert!(!state.can(1, 2, Mode::empty()));
+}
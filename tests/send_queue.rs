@@ -0,0 +1,203 @@
+extern crate synac;
+
+mod fixtures;
+
+use fixtures::{clock, typing};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::io::{self, Cursor, Read, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use synac::common::{self, *};
+use synac::{QueuedSession, SendQueue, Session};
+
+fn channel(packet: Option<Packet>) -> Option<usize> {
+    match packet {
+        Some(Packet::Typing(event)) => Some(event.channel),
+        None => None,
+        Some(packet) => panic!("unexpected packet {:?}", packet)
+    }
+}
+fn advance(now: &Cell<Instant>, millis: u64) {
+    fixtures::advance(now, Duration::from_millis(millis));
+}
+
+#[test]
+fn retries_after_rate_limit() {
+    let (now, clock) = clock();
+    let mut queue = SendQueue::with_clock(clock);
+    assert_eq!(queue.delay(), None);
+    for i in 0..4 {
+        queue.push(typing(i));
+    }
+    assert_eq!(channel(queue.pop()), Some(0));
+    assert_eq!(channel(queue.pop()), Some(1));
+    assert_eq!(channel(queue.pop()), Some(2));
+
+    // the last two were dropped
+    assert!(queue.update(&Packet::RateLimited(2)));
+    advance(&now, 500);
+    assert!(queue.update(&Packet::RateLimited(2)));
+    assert!(!queue.update(&Packet::MessageListReceived));
+    assert!(queue.is_paused());
+    assert_eq!(channel(queue.pop()), None);
+    assert_eq!(queue.delay(), Some(Duration::from_millis(1500)));
+
+    advance(&now, 1500);
+    assert_eq!(channel(queue.pop()), Some(1));
+    assert_eq!(channel(queue.pop()), Some(2));
+    assert_eq!(channel(queue.pop()), Some(3));
+    assert_eq!(channel(queue.pop()), None);
+
+    let stats = queue.stats();
+    assert_eq!((stats.queued, stats.sent, stats.retried, stats.lost, stats.rate_limited), (0, 6, 2, 0, 2));
+    assert_eq!(stats.max_wait, Duration::from_millis(2000));
+}
+#[test]
+fn token_bucket() {
+    let (now, clock) = clock();
+    let mut queue = SendQueue::with_clock(clock).token_bucket(2, Duration::from_millis(100));
+    for i in 0..5 {
+        queue.push(typing(i));
+    }
+    assert_eq!(channel(queue.pop()), Some(0));
+    assert_eq!(channel(queue.pop()), Some(1));
+    assert_eq!(channel(queue.pop()), None);
+    assert_eq!(queue.delay(), Some(Duration::from_millis(100)));
+    assert_eq!(queue.stats().queued, 3);
+
+    advance(&now, 250);
+    assert_eq!(channel(queue.pop()), Some(2));
+    assert_eq!(channel(queue.pop()), Some(3));
+    assert_eq!(channel(queue.pop()), None);
+    // the bucket was full, so the extra 50ms didn't count
+    assert_eq!(queue.delay(), Some(Duration::from_millis(100)));
+
+    advance(&now, 100);
+    assert_eq!(channel(queue.pop()), Some(4));
+    assert!(queue.is_empty());
+    assert_eq!(queue.stats().max_queued, 5);
+}
+#[test]
+fn recorded_packets_are_retried_instead() {
+    let (now, clock) = clock();
+    let mut queue = SendQueue::with_clock(clock);
+    for i in 0..3 {
+        queue.push(typing(i));
+    }
+    assert_eq!(channel(queue.pop()), Some(0));
+    assert_eq!(channel(queue.pop()), Some(1));
+    // sent directly, and then dropped
+    queue.record(typing(10));
+    assert!(queue.update(&Packet::RateLimited(1)));
+
+    advance(&now, 1000);
+    assert_eq!(channel(queue.pop()), Some(10));
+    assert_eq!(channel(queue.pop()), Some(2));
+    assert_eq!(channel(queue.pop()), None);
+    assert_eq!(queue.stats().retried, 1);
+}
+
+/// A non-blocking transport that replies with whatever is in `input` and keeps everything written
+struct Recording {
+    input: Rc<RefCell<VecDeque<u8>>>,
+    output: Rc<RefCell<Vec<u8>>>
+}
+
+impl Read for Recording {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut input = self.input.borrow_mut();
+        if input.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let len = buf.len().min(input.len());
+        for (dst, src) in buf.iter_mut().zip(input.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+impl Write for Recording {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn queued_session_interleaves_writes() {
+    let (now, clock) = clock();
+    let mut input = Vec::new();
+    common::write(&mut input, &Packet::RateLimited(1)).unwrap();
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let input = Rc::new(RefCell::new(VecDeque::from(input)));
+    let session = Session::from_stream(Recording { input, output: Rc::clone(&output) });
+    let mut session = QueuedSession::new(session, SendQueue::with_clock(clock));
+
+    session.push(typing(0));
+    session.flush().unwrap();
+    session.write(&typing(10)).unwrap();
+    session.push(typing(1));
+    // the direct write was the one dropped, not the queued packet before it
+    assert!(matches!(session.read().unwrap(), Packet::RateLimited(1)));
+    session.flush().unwrap();
+    advance(&now, 1000);
+    session.flush().unwrap();
+
+    let mut written = Cursor::new(output.borrow().clone());
+    let channels: Vec<_> = (0..4).map(|_| channel(Some(common::read(&mut written).unwrap()))).collect();
+    assert_eq!(channels, [Some(0), Some(10), Some(10), Some(1)]);
+    assert!(common::read(&mut written).is_err());
+}
+#[test]
+fn queued_session_reads_without_blocking() {
+    let (now, clock) = clock();
+    let mut frame = Vec::new();
+    common::write(&mut frame, &Packet::RateLimited(1)).unwrap();
+    let input = Rc::new(RefCell::new(VecDeque::new()));
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let session = Session::from_stream(Recording { input: Rc::clone(&input), output: Rc::clone(&output) });
+    let mut session = QueuedSession::new(session, SendQueue::with_clock(clock));
+
+    session.push(typing(0));
+    session.flush().unwrap();
+    assert!(session.try_read().unwrap().is_none());
+
+    // half a packet arrives, then the rest
+    input.borrow_mut().extend(&frame[..1]);
+    assert!(session.try_read().unwrap().is_none());
+    input.borrow_mut().extend(&frame[1..]);
+    assert!(matches!(session.try_read().unwrap(), Some(Packet::RateLimited(1))));
+    assert!(session.queue().is_paused());
+    assert!(session.try_read().unwrap().is_none());
+
+    advance(&now, 1000);
+    session.flush().unwrap();
+    let mut written = Cursor::new(output.borrow().clone());
+    assert_eq!(channel(Some(common::read(&mut written).unwrap())), Some(0));
+    assert_eq!(channel(Some(common::read(&mut written).unwrap())), Some(0));
+}
+#[test]
+fn token_bucket_limits() {
+    // a burst of 0 still sends
+    let (now, clock) = clock();
+    let mut queue = SendQueue::with_clock(clock).token_bucket(0, Duration::from_millis(100));
+    queue.push(typing(0));
+    queue.push(typing(1));
+    assert_eq!(channel(queue.pop()), Some(0));
+    assert_eq!(channel(queue.pop()), None);
+    advance(&now, 100);
+    assert_eq!(channel(queue.pop()), Some(1));
+
+    // more intervals than fit in a u32 still refill the bucket
+    let (now, clock) = fixtures::clock();
+    let mut queue = SendQueue::with_clock(clock).token_bucket(1, Duration::from_nanos(1));
+    queue.push(typing(0));
+    queue.push(typing(1));
+    assert_eq!(channel(queue.pop()), Some(0));
+    fixtures::advance(&now, Duration::from_nanos(1 << 32));
+    assert_eq!(channel(queue.pop()), Some(1));
+}
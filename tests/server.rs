@@ -0,0 +1,212 @@
+#![cfg(feature = "server")]
+extern crate synac;
+
+use synac::common::*;
+use synac::server::Server;
+use synac::testing::Pipe;
+use synac::{Direction, Mode, ServerError, Session};
+
+type MemorySession = Session<Pipe>;
+
+fn login(server: &Server, name: &str, bot: bool) -> (MemorySession, usize) {
+    let mut session = server.connect();
+    let outcome = session.login_password(bot, name, "hunter2").unwrap();
+    (session, outcome.id)
+}
+/// Read until `select` picks a packet, skipping everything else
+fn wait_for<T, F: FnMut(Packet) -> Option<T>>(session: &mut MemorySession, mut select: F) -> T {
+    loop {
+        if let Some(value) = select(session.read().unwrap()) {
+            return value;
+        }
+    }
+}
+fn wait_for_err(session: &mut MemorySession) -> u8 {
+    wait_for(session, |packet| match packet {
+        Packet::Err(code) => Some(code),
+        _ => None
+    })
+}
+fn wait_for_channel(session: &mut MemorySession) -> Channel {
+    wait_for(session, |packet| match packet {
+        Packet::ChannelReceive(event) => Some(event.inner),
+        _ => None
+    })
+}
+
+#[test]
+fn accounts() {
+    let server = Server::new();
+    let mut session = server.connect();
+    let created = session.login_password(false, "alice", "hunter2").unwrap();
+    assert!(created.created);
+    drop(session);
+
+    let mut session = server.connect();
+    let outcome = session.login(false, "alice", &*created.token).unwrap();
+    assert_eq!((outcome.id, outcome.created), (created.id, false));
+
+    let mut session = server.connect();
+    let err = session.login_password(false, "alice", "wrong").unwrap_err();
+    assert_eq!(err.downcast::<ServerError>().unwrap(), ServerError::LoginInvalid);
+
+    let mut session = server.connect();
+    let err = session.login_password(true, "alice", "hunter2").unwrap_err();
+    assert_eq!(err.downcast::<ServerError>().unwrap(), ServerError::LoginBot);
+
+    let mut session = server.connect();
+    session.send_typing(1).unwrap();
+    assert_eq!(wait_for_err(&mut session), ERR_MISSING_PERMISSION);
+}
+
+#[test]
+fn private_channels() {
+    let server = Server::new();
+    let (mut alice, alice_id) = login(&server, "alice", false);
+    let (mut bob, bob_id) = login(&server, "bob", false);
+
+    alice.create_private_channel(alice_id).unwrap();
+    assert_eq!(wait_for_err(&mut alice), ERR_SELF_PM);
+
+    alice.create_private_channel(bob_id).unwrap();
+    let channel = wait_for_channel(&mut alice);
+    assert!(channel.private);
+    assert_eq!(wait_for_channel(&mut bob).id, channel.id);
+
+    alice.create_private_channel(bob_id).unwrap();
+    assert_eq!(wait_for_err(&mut alice), ERR_ALREADY_EXISTS);
+
+    alice.send_typing(channel.id).unwrap();
+    let typing = wait_for(&mut bob, |packet| match packet {
+        Packet::TypingReceive(event) => Some(event),
+        _ => None
+    });
+    assert_eq!((typing.author, typing.channel), (alice_id, channel.id));
+
+    let (mut eve, _) = login(&server, "eve", false);
+    eve.send_message(channel.id, "hi").unwrap();
+    assert_eq!(wait_for_err(&mut eve), ERR_MISSING_PERMISSION);
+}
+
+#[test]
+fn messages() {
+    let server = Server::new();
+    let (mut admin, admin_id) = login(&server, "admin", false);
+    server.set_admin(admin_id, true);
+    let (mut user, _) = login(&server, "user", false);
+
+    admin.create_channel("general", PERM_READ.into(), (PERM_READ | PERM_WRITE).into()).unwrap();
+    let channel = wait_for_channel(&mut admin);
+    wait_for_channel(&mut user);
+
+    user.create_channel("mine", PERM_READ.into(), PERM_READ.into()).unwrap();
+    assert_eq!(wait_for_err(&mut user), ERR_MISSING_PERMISSION);
+
+    for i in 0..10 {
+        user.send_message(channel.id, format!("message {}", i)).unwrap();
+    }
+    // messages from another connection may not be handled yet
+    wait_for(&mut admin, |packet| match packet {
+        Packet::MessageReceive(ref event) if event.inner.text == b"message 9" => Some(()),
+        _ => None
+    });
+    let history: Vec<_> = admin.history(channel.id, Direction::Backwards, 3)
+        .map(|message| String::from_utf8(message.unwrap().text).unwrap())
+        .collect();
+    assert_eq!(history.len(), 10);
+    assert_eq!(history[0], "message 9");
+    assert_eq!(history[9], "message 0");
+
+    let ids: Vec<usize> = admin.history(channel.id, Direction::Forwards, LIMIT_BULK)
+        .map(|message| message.unwrap().id)
+        .collect();
+    user.delete_messages(channel.id, ids.clone()).unwrap();
+    assert_eq!(wait_for_err(&mut user), ERR_MISSING_PERMISSION);
+
+    admin.delete_messages(channel.id, vec![ids[0]; LIMIT_BULK + 1]).unwrap();
+    assert_eq!(wait_for_err(&mut admin), ERR_LIMIT_REACHED);
+
+    admin.delete_messages(channel.id, ids).unwrap();
+    assert_eq!(admin.history(channel.id, Direction::Forwards, 5).count(), 0);
+}
+
+#[test]
+fn commands() {
+    let server = Server::new();
+    let (mut user, user_id) = login(&server, "user", false);
+    let (mut bot, bot_id) = login(&server, "bot", true);
+
+    user.send_command(user_id, vec![String::from("help")]).unwrap();
+    assert_eq!(wait_for_err(&mut user), ERR_UNKNOWN_BOT);
+
+    user.send_command(bot_id, vec![String::from("help")]).unwrap();
+    let command = wait_for(&mut bot, |packet| match packet {
+        Packet::CommandReceive(event) => Some(event),
+        _ => None
+    });
+    assert_eq!((command.author, &*command.args), (user_id, &[String::from("help")][..]));
+}
+
+#[test]
+fn persistence() {
+    let path = std::env::temp_dir().join(format!("synac-server-test-{}", std::process::id()));
+    let server = Server::open(&path).unwrap();
+    let (_session, id) = login(&server, "alice", false);
+    server.save().unwrap();
+
+    let server = Server::open(&path).unwrap();
+    assert_eq!(server.user_by_name("alice").map(|user| user.id), Some(id));
+    let (_session, again) = login(&server, "alice", false);
+    assert_eq!(again, id);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn permissions() {
+    let server = Server::new();
+    let (mut admin, admin_id) = login(&server, "admin", false);
+    server.set_admin(admin_id, true);
+    let (mut user, user_id) = login(&server, "user", false);
+    let (mut bot, bot_id) = login(&server, "bot", true);
+
+    admin.create_channel("bots", PERM_READ.into(), (PERM_READ | PERM_WRITE).into()).unwrap();
+    let public = wait_for_channel(&mut admin);
+    user.create_private_channel(bot_id).unwrap();
+    let private = wait_for_channel(&mut user);
+
+    // the server and `get_effective_mode` agree on who may write
+    let check = |session: &mut MemorySession, id: usize, channel: &Channel| {
+        let allowed = synac::get_effective_mode(channel, &server.user(id).unwrap()).contains(Mode::WRITE);
+        session.send_typing(channel.id).unwrap();
+        // always fails, so there's an error to wait for either way
+        session.create_private_channel(id).unwrap();
+        let code = wait_for_err(session);
+        assert_eq!(code == ERR_SELF_PM, allowed, "user {} in {}", id, channel.name);
+        if code != ERR_SELF_PM {
+            assert_eq!(code, ERR_MISSING_PERMISSION);
+            assert_eq!(wait_for_err(session), ERR_SELF_PM);
+        }
+    };
+    check(&mut admin, admin_id, &public);
+    check(&mut admin, admin_id, &private);
+    check(&mut user, user_id, &public);
+    check(&mut user, user_id, &private);
+    check(&mut bot, bot_id, &public);
+    check(&mut bot, bot_id, &private);
+}
+
+#[test]
+fn bans() {
+    let server = Server::new();
+    let (mut admin, admin_id) = login(&server, "admin", false);
+    server.set_admin(admin_id, true);
+    let (mut user, user_id) = login(&server, "user", false);
+
+    admin.set_ban(user_id, true).unwrap();
+    // whatever was sent before the ban can still be read, then the connection is closed
+    while user.read().is_ok() {}
+
+    let mut session = server.connect();
+    let err = session.login_password(false, "user", "hunter2").unwrap_err();
+    assert_eq!(err.downcast::<ServerError>().unwrap(), ServerError::LoginBanned);
+}
@@ -0,0 +1,63 @@
+extern crate synac;
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use synac::common::{self, *};
+use synac::Session;
+
+/// Returns each chunk from a separate read, and times out between them
+struct Trickle(VecDeque<Option<Vec<u8>>>);
+
+impl Read for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.pop_front() {
+            Some(Some(mut chunk)) => {
+                let len = chunk.len().min(buf.len());
+                buf[..len].copy_from_slice(&chunk[..len]);
+                if len < chunk.len() {
+                    self.0.push_front(Some(chunk.split_off(len)));
+                }
+                Ok(len)
+            },
+            Some(None) => Err(io::ErrorKind::WouldBlock.into()),
+            None => Ok(0)
+        }
+    }
+}
+impl Write for Trickle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn frame(channel: usize) -> Vec<u8> {
+    let mut buf = Vec::new();
+    common::write(&mut buf, &Packet::Typing(Typing { channel })).unwrap();
+    buf
+}
+fn channel(packet: Packet) -> usize {
+    match packet {
+        Packet::Typing(event) => event.channel,
+        packet => panic!("unexpected packet {:?}", packet)
+    }
+}
+
+#[test]
+fn read_timeout_keeps_partial_packet() {
+    let (first, second) = (frame(1), frame(2));
+    let mut session = Session::from_stream(Trickle(VecDeque::from(vec![
+        Some(first[..1].to_vec()),
+        None,
+        Some(first[1..4].to_vec()),
+        None,
+        Some(first[4..].iter().chain(&second).cloned().collect())
+    ])));
+    assert!(session.read().is_err());
+    assert!(session.read().is_err());
+    assert_eq!(channel(session.read().unwrap()), 1);
+    assert_eq!(channel(session.read().unwrap()), 2);
+    assert!(session.read().is_err());
+}
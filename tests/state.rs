@@ -0,0 +1,29 @@
+extern crate synac;
+
+mod fixtures;
+
+use fixtures::{login, receive_channel, receive_user};
+use synac::common::*;
+use synac::{Mode, State};
+
+#[test]
+fn me() {
+    let mut state = State::new();
+    state.update(&receive_channel(Channel { default_mode_user: PERM_READ, ..fixtures::channel(1, "channel") }));
+
+    // the id is known right away, the user only once it's received
+    state.update(&login(2));
+    assert_eq!(state.id, Some(2));
+    assert!(state.me().is_none());
+    assert_eq!(state.my_mode(1), None);
+    assert!(!state.is_admin());
+
+    state.update(&receive_user(fixtures::user(1)));
+    assert!(state.me().is_none());
+
+    state.update(&receive_user(User { admin: true, ..fixtures::user(2) }));
+    assert_eq!(state.me().unwrap().name, "user2");
+    assert!(state.is_admin());
+    assert_eq!(state.my_mode(1), Some(Mode::ALL));
+    assert_eq!(state.my_mode(3), None);
+}
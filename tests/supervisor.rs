@@ -0,0 +1,178 @@
+extern crate synac;
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::io::{self, Cursor, Read, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::option;
+use std::rc::Rc;
+use std::time::Duration;
+use synac::common::{self, *};
+use synac::{Event, MissingServerName, ServerError, Session, Supervisor};
+
+/// Replies with scripted bytes, then fails like a dropped connection
+struct Scripted(Cursor<Vec<u8>>);
+
+impl Read for Scripted {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+impl Write for Scripted {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Attempt {
+    Refused,
+    Reply(Vec<Packet>)
+}
+
+fn connecting(attempts: Vec<Attempt>) -> Supervisor<Scripted> {
+    let attempts = Rc::new(RefCell::new(VecDeque::from(attempts)));
+    let mut supervisor = Supervisor::with_connector(move || {
+        match attempts.borrow_mut().pop_front().unwrap_or(Attempt::Refused) {
+            Attempt::Refused => Err(io::Error::from(io::ErrorKind::ConnectionRefused).into()),
+            Attempt::Reply(packets) => {
+                let mut input = Vec::new();
+                for packet in &packets {
+                    common::write(&mut input, packet).unwrap();
+                }
+                Ok(Session::from_stream(Scripted(Cursor::new(input))))
+            }
+        }
+    }, true, "bot", "token");
+    supervisor.set_backoff(Duration::from_millis(1), Duration::from_millis(8));
+    supervisor
+}
+fn login(token: &str) -> Packet {
+    Packet::LoginSuccess(LoginSuccess { created: false, id: 1, token: token.to_string() })
+}
+
+#[test]
+fn reconnects() {
+    let mut supervisor = connecting(vec![
+        Attempt::Refused,
+        Attempt::Reply(vec![login("new"), Packet::MessageListReceived])
+    ]);
+    match supervisor.next_event().unwrap() {
+        Event::Disconnected(_) => (),
+        event => panic!("unexpected event {:?}", event)
+    }
+    assert!(supervisor.session().is_none());
+    match supervisor.next_event().unwrap() {
+        Event::Reconnecting { attempt: 1, .. } => (),
+        event => panic!("unexpected event {:?}", event)
+    }
+    match supervisor.next_event().unwrap() {
+        Event::Connected(outcome) => assert_eq!(outcome.token, "new"),
+        event => panic!("unexpected event {:?}", event)
+    }
+    assert_eq!(supervisor.token(), "new");
+
+    // the login result is buffered so the state learns about it too
+    match supervisor.next_event().unwrap() {
+        Event::Packet(Packet::LoginSuccess(_)) => (),
+        event => panic!("unexpected event {:?}", event)
+    }
+    assert_eq!(supervisor.state().id, Some(1));
+    match supervisor.next_event().unwrap() {
+        Event::Packet(Packet::MessageListReceived) => (),
+        event => panic!("unexpected event {:?}", event)
+    }
+
+    // the connection drops and the attempts start over
+    match supervisor.next_event().unwrap() {
+        Event::Disconnected(_) => (),
+        event => panic!("unexpected event {:?}", event)
+    }
+    match supervisor.next_event().unwrap() {
+        Event::Reconnecting { attempt: 1, .. } => (),
+        event => panic!("unexpected event {:?}", event)
+    }
+}
+#[test]
+fn backoff_grows_with_jitter_up_to_max() {
+    let mut supervisor = connecting(Vec::new());
+    supervisor.set_backoff(Duration::from_micros(1), Duration::from_micros(8));
+    let mut attempts = 0;
+    // enough attempts for the jitter to cover its range
+    while attempts < 500 {
+        match supervisor.next_event().unwrap() {
+            Event::Disconnected(_) => (),
+            Event::Reconnecting { attempt, delay } => {
+                attempts += 1;
+                assert_eq!(attempt, attempts);
+                // doubling each attempt until the cap, and jitter takes off up to half
+                let max = Duration::from_micros(1 << (attempt - 1).min(3));
+                assert!(delay <= max && delay >= max / 2, "attempt {} waited {:?}", attempt, delay);
+            },
+            event => panic!("unexpected event {:?}", event)
+        }
+    }
+}
+#[test]
+fn gives_up_on_fatal_errors() {
+    let mut supervisor = connecting(vec![Attempt::Reply(vec![Packet::Err(ERR_LOGIN_INVALID)])]);
+    let err = supervisor.next_event().unwrap_err();
+    assert_eq!(err.downcast::<ServerError>().unwrap(), ServerError::LoginInvalid);
+
+    // unlike too many connections, which can go away
+    let mut supervisor = connecting(vec![Attempt::Reply(vec![Packet::Err(ERR_MAX_CONN_PER_IP)])]);
+    match supervisor.next_event().unwrap() {
+        Event::Disconnected(err) => assert_eq!(err.downcast::<ServerError>().unwrap(), ServerError::MaxConnPerIp),
+        event => panic!("unexpected event {:?}", event)
+    }
+
+    // so is a builder that can't verify the server
+    let mut supervisor = Supervisor::<Scripted>::with_connector(|| Err(MissingServerName.into()), true, "bot", "token");
+    let err = supervisor.next_event().unwrap_err();
+    assert_eq!(err.downcast::<MissingServerName>().unwrap(), MissingServerName);
+}
+#[test]
+fn waits_out_rate_limits() {
+    let mut supervisor = connecting(vec![Attempt::Reply(vec![Packet::RateLimited(1)])]);
+    match supervisor.next_event().unwrap() {
+        Event::Disconnected(_) => (),
+        event => panic!("unexpected event {:?}", event)
+    }
+    match supervisor.next_event().unwrap() {
+        Event::Reconnecting { delay, .. } => assert_eq!(delay, Duration::from_secs(1)),
+        event => panic!("unexpected event {:?}", event)
+    }
+}
+
+/// Counts how often it's resolved
+struct Resolving(Rc<Cell<usize>>);
+
+impl ToSocketAddrs for Resolving {
+    type Iter = option::IntoIter<SocketAddr>;
+
+    fn to_socket_addrs(&self) -> io::Result<Self::Iter> {
+        self.0.set(self.0.get() + 1);
+        Ok(Some(SocketAddr::from(([127, 0, 0, 1], 1))).into_iter())
+    }
+}
+
+#[test]
+fn resolves_on_every_attempt() {
+    let resolved = Rc::new(Cell::new(0));
+    let mut supervisor = Supervisor::new(Resolving(Rc::clone(&resolved)), "hash", true, "bot", "token").unwrap();
+    supervisor.set_backoff(Duration::from_millis(1), Duration::from_millis(1));
+    assert_eq!(resolved.get(), 0);
+    for attempt in 1..=2 {
+        match supervisor.next_event().unwrap() {
+            Event::Disconnected(_) => (),
+            event => panic!("unexpected event {:?}", event)
+        }
+        assert_eq!(resolved.get(), attempt);
+        match supervisor.next_event().unwrap() {
+            Event::Reconnecting { .. } => (),
+            event => panic!("unexpected event {:?}", event)
+        }
+    }
+}
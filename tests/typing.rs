@@ -0,0 +1,67 @@
+extern crate synac;
+
+mod fixtures;
+
+use fixtures::{advance, clock, receive_message};
+use std::time::Duration;
+use synac::common::*;
+use synac::{typing_timeout, TypingThrottle, TypingTracker};
+
+fn typing(author: usize, channel: usize) -> Packet {
+    Packet::TypingReceive(TypingReceive { author, channel })
+}
+
+#[test]
+fn tracker_expires() {
+    let (now, clock) = clock();
+    let mut tracker = TypingTracker::with_clock(clock);
+    tracker.update(&typing(1, 10));
+    advance(&now, typing_timeout() / 2);
+    tracker.update(&typing(2, 10));
+    assert_eq!(tracker.typing_in(10), [1, 2]);
+    assert!(tracker.is_typing(1, 10));
+    assert!(!tracker.is_typing(1, 11));
+
+    advance(&now, typing_timeout() / 2);
+    assert!(!tracker.is_typing(1, 10));
+    assert_eq!(tracker.typing_in(10), [2]);
+
+    // typing again restarts the timeout
+    tracker.update(&typing(2, 10));
+    advance(&now, typing_timeout() - Duration::from_millis(1));
+    tracker.expire();
+    assert_eq!(tracker.typing_in(10), [2]);
+    advance(&now, Duration::from_millis(1));
+    tracker.expire();
+    assert!(tracker.typing_in(10).is_empty());
+}
+#[test]
+fn tracker_stops_on_message() {
+    let (_, clock) = clock();
+    let mut tracker = TypingTracker::with_clock(clock);
+    tracker.update(&typing(1, 10));
+    tracker.update(&typing(2, 10));
+    tracker.update(&receive_message(fixtures::message(1, 10, 1), true));
+    assert_eq!(tracker.typing_in(10), [2]);
+
+    tracker.update(&Packet::ChannelDeleteReceive(ChannelDeleteReceive {
+        inner: Channel { id: 10, ..Default::default() }
+    }));
+    assert!(tracker.typing_in(10).is_empty());
+}
+#[test]
+fn throttle_window() {
+    let (now, clock) = clock();
+    let mut throttle = TypingThrottle::with_clock(clock);
+    assert!(throttle.typing(10).is_some());
+    assert!(throttle.typing(10).is_none());
+    assert!(throttle.typing(11).is_some());
+
+    advance(&now, typing_timeout() - Duration::from_millis(1));
+    assert!(throttle.typing(10).is_none());
+    advance(&now, Duration::from_millis(1));
+    assert!(throttle.typing(10).is_some());
+
+    throttle.reset(10);
+    assert!(throttle.typing(10).is_some());
+}